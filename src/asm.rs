@@ -0,0 +1,639 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use crate::disasm::START;
+use crate::hardware::instruction::Op;
+
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+const MNEMONICS: [&str; 33] = [
+    "CLS", "RET", "SYS", "JP", "CALL", "SE", "SNE", "LD", "ADD", "OR", "AND", "XOR", "SUB",
+    "SHR", "SUBN", "SHL", "RND", "DRW", "SKP", "SKNP", "SCD", "SCR", "SCL", "EXIT", "LOW",
+    "HIGH", "SCU", "SAVE", "LOAD", "PLANE", "AUDIO", "DB", "DW",
+];
+
+/// A problem in the source, `line` and `column` start at 1
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{}:{}:{}: {}", self.file, self.line, self.column, self.message);
+    }
+}
+
+impl Error for AsmError {}
+
+/// Assemble Cowgod style source, the same dialect `disasm` writes.
+/// Includes are resolved relative to the current directory.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let mut asm = Assembler::new();
+    asm.read_source(source, "<input>", Path::new("."), 0)?;
+    return asm.finish();
+}
+
+/// Assemble a source file, includes are resolved relative to it
+pub fn assemble_file(path: &Path) -> Result<Vec<u8>, AsmError> {
+    let mut asm = Assembler::new();
+    asm.read_file(path, None, 0)?;
+    return asm.finish();
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    text: String,
+    file: String,
+    line: usize,
+    column: usize,
+}
+
+impl Token {
+    fn error(&self, message: String) -> AsmError {
+        return AsmError {
+            file: self.file.clone(),
+            line: self.line,
+            column: self.column,
+            message,
+        };
+    }
+
+    fn keyword(&self) -> String {
+        return self.text.to_ascii_uppercase();
+    }
+}
+
+struct Statement {
+    addr: u16,
+    mnemonic: Token,
+    operands: Vec<Token>,
+}
+
+struct Assembler {
+    statements: Vec<Statement>,
+    labels: HashMap<String, (u16, Token)>,
+    constants: HashMap<String, Token>,
+    addr: usize,
+}
+
+impl Assembler {
+    fn new() -> Self {
+        return Assembler {
+            statements: Vec::new(),
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            addr: START as usize,
+        };
+    }
+
+    fn read_file(&mut self, path: &Path, from: Option<&Token>, depth: usize) -> Result<(), AsmError> {
+        let source = fs::read_to_string(path).map_err(|e| {
+            let message = format!("couldn't read {}: {}", path.display(), e);
+            match from {
+                Some(token) => token.error(message),
+                None => AsmError { file: path.display().to_string(), line: 0, column: 0, message },
+            }
+        })?;
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        return self.read_source(&source, &path.display().to_string(), dir, depth);
+    }
+
+    /// First pass: split lines into statements and give every label an address
+    fn read_source(&mut self, source: &str, file: &str, dir: &Path, depth: usize) -> Result<(), AsmError> {
+        for (number, text) in source.lines().enumerate() {
+            let mut tokens = split_line(text, file, number + 1);
+            if tokens.is_empty() {
+                continue;
+            }
+
+            if tokens[0].text.ends_with(':') {
+                let mut label = tokens.remove(0);
+                label.text.pop();
+                self.define_label(label)?;
+                if tokens.is_empty() {
+                    continue;
+                }
+            }
+
+            if tokens.len() >= 3 && tokens[1].keyword() == "EQU" {
+                let name = tokens.remove(0);
+                tokens.remove(0);
+                if tokens.len() > 1 {
+                    return Err(tokens[1].error(format!("unexpected {}", tokens[1].text)));
+                }
+                if self.constants.contains_key(&name.text) || self.labels.contains_key(&name.text) {
+                    return Err(name.error(format!("{} is already defined", name.text)));
+                }
+                self.constants.insert(name.text, tokens.remove(0));
+                continue;
+            }
+
+            let mnemonic = tokens.remove(0);
+            let addr = self.addr as u16;
+            match mnemonic.keyword().as_str() {
+                "INCLUDE" => {
+                    let path = match tokens.first() {
+                        Some(t) if t.text.len() >= 2 && t.text.starts_with('"') && t.text.ends_with('"') => {
+                            dir.join(&t.text[1..t.text.len() - 1])
+                        },
+                        _ => return Err(mnemonic.error(String::from("include expects a quoted file name"))),
+                    };
+                    if depth >= MAX_INCLUDE_DEPTH {
+                        return Err(mnemonic.error(String::from("includes nested too deeply")));
+                    }
+                    self.read_file(&path, Some(&mnemonic), depth + 1)?;
+                    continue;
+                },
+                "DB" => self.reserve(&mnemonic, tokens.iter().map(data_len).sum::<Result<usize, AsmError>>()?)?,
+                "DW" => self.reserve(&mnemonic, tokens.len() * 2)?,
+                "LD" if tokens.len() == 2 && tokens[0].keyword() == "I" && is_long(&tokens[1]) => {
+                    self.reserve(&mnemonic, 4)?
+                },
+                _ => self.reserve(&mnemonic, 2)?,
+            }
+            self.statements.push(Statement {
+                addr,
+                mnemonic,
+                operands: tokens,
+            });
+        }
+        return Ok(());
+    }
+
+    fn define_label(&mut self, label: Token) -> Result<(), AsmError> {
+        if label.text.is_empty() || parse_register(&label.text).is_some() {
+            return Err(label.error(format!("{} can't be used as a label", label.text)));
+        }
+        if let Some((_, first)) = self.labels.get(&label.text) {
+            return Err(label.error(format!("{} is already defined at {}:{}", label.text, first.file, first.line)));
+        }
+        if self.constants.contains_key(&label.text) {
+            return Err(label.error(format!("{} is already defined", label.text)));
+        }
+        self.labels.insert(label.text.clone(), (self.addr as u16, label));
+        return Ok(());
+    }
+
+    fn reserve(&mut self, at: &Token, len: usize) -> Result<(), AsmError> {
+        self.addr += len;
+        if self.addr > 0x10000 {
+            return Err(at.error(String::from("program doesn't fit in memory")));
+        }
+        return Ok(());
+    }
+
+    /// Second pass: encode every statement now all labels are known
+    fn finish(&self) -> Result<Vec<u8>, AsmError> {
+        let mut rom = Vec::new();
+        for statement in self.statements.iter() {
+            debug_assert_eq!(START as usize + rom.len(), statement.addr as usize);
+            let ops = &statement.operands;
+            match statement.mnemonic.keyword().as_str() {
+                "DB" => {
+                    for op in ops.iter() {
+                        if op.text.starts_with('"') {
+                            rom.extend(op.text[1..op.text.len() - 1].bytes());
+                        } else {
+                            rom.push(self.byte(op)?);
+                        }
+                    }
+                },
+                "DW" => {
+                    for op in ops.iter() {
+                        let word = self.value(op, 0, 0xFFFF)? as u16;
+                        rom.push((word >> 8) as u8);
+                        rom.push(word as u8);
+                    }
+                },
+                _ => {
+                    let op = self.instruction(&statement.mnemonic, ops)?;
+                    let opcode = op.encode();
+                    rom.push((opcode >> 8) as u8);
+                    rom.push(opcode as u8);
+                    if op == Op::LdILong {
+                        let long = ops[1].text[4..].trim_start();
+                        let token = Token { text: long.to_string(), column: ops[1].column + 5, ..ops[1].clone() };
+                        let addr = self.value(&token, 0, 0xFFFF)? as u16;
+                        rom.push((addr >> 8) as u8);
+                        rom.push(addr as u8);
+                    }
+                },
+            }
+        }
+        return Ok(rom);
+    }
+
+    fn instruction(&self, mnemonic: &Token, ops: &[Token]) -> Result<Op, AsmError> {
+        let name = mnemonic.keyword();
+        let op = match (name.as_str(), ops.len()) {
+            ("CLS", 0) => Op::Cls,
+            ("RET", 0) => Op::Ret,
+            ("SCR", 0) => Op::Scr,
+            ("SCL", 0) => Op::Scl,
+            ("EXIT", 0) => Op::Exit,
+            ("LOW", 0) => Op::Low,
+            ("HIGH", 0) => Op::High,
+            ("AUDIO", 0) => Op::Audio,
+            ("SYS", 1) => Op::Sys(self.addr(&ops[0])?),
+            ("JP", 1) => Op::Jp(self.addr(&ops[0])?),
+            ("JP", 2) => {
+                if self.register(&ops[0])? != 0 {
+                    return Err(ops[0].error(String::from("JP can only be offset by V0")));
+                }
+                Op::JpV0(self.addr(&ops[1])?)
+            },
+            ("CALL", 1) => Op::Call(self.addr(&ops[0])?),
+            ("SE", 2) | ("SNE", 2) => {
+                let x = self.register(&ops[0])?;
+                match (name.as_str(), parse_register(&ops[1].text)) {
+                    ("SE", Some(y)) => Op::SeReg(x, y),
+                    ("SE", None) => Op::SeByte(x, self.byte(&ops[1])?),
+                    (_, Some(y)) => Op::SneReg(x, y),
+                    (_, None) => Op::SneByte(x, self.byte(&ops[1])?),
+                }
+            },
+            ("ADD", 2) if ops[0].keyword() == "I" => Op::AddI(self.register(&ops[1])?),
+            ("ADD", 2) => {
+                let x = self.register(&ops[0])?;
+                match parse_register(&ops[1].text) {
+                    Some(y) => Op::AddReg(x, y),
+                    None => Op::AddByte(x, self.byte(&ops[1])?),
+                }
+            },
+            ("OR", 2) => Op::Or(self.register(&ops[0])?, self.register(&ops[1])?),
+            ("AND", 2) => Op::And(self.register(&ops[0])?, self.register(&ops[1])?),
+            ("XOR", 2) => Op::Xor(self.register(&ops[0])?, self.register(&ops[1])?),
+            ("SUB", 2) => Op::Sub(self.register(&ops[0])?, self.register(&ops[1])?),
+            ("SUBN", 2) => Op::Subn(self.register(&ops[0])?, self.register(&ops[1])?),
+            // Without VY, shift VX in place whichever shift quirk is in use
+            ("SHR", 1) | ("SHL", 1) | ("SHR", 2) | ("SHL", 2) => {
+                let x = self.register(&ops[0])?;
+                let y = match ops.get(1) {
+                    Some(op) => self.register(op)?,
+                    None => x,
+                };
+                if name == "SHR" { Op::Shr(x, y) } else { Op::Shl(x, y) }
+            },
+            ("RND", 2) => Op::Rnd(self.register(&ops[0])?, self.byte(&ops[1])?),
+            ("DRW", 3) => Op::Drw(
+                self.register(&ops[0])?,
+                self.register(&ops[1])?,
+                self.value(&ops[2], 0, 0xF)? as u8,
+            ),
+            ("SKP", 1) => Op::Skp(self.register(&ops[0])?),
+            ("SKNP", 1) => Op::Sknp(self.register(&ops[0])?),
+            ("SCD", 1) => Op::Scd(self.value(&ops[0], 0, 0xF)? as u8),
+            ("SCU", 1) => Op::Scu(self.value(&ops[0], 0, 0xF)? as u8),
+            ("PLANE", 1) => Op::Plane(self.value(&ops[0], 0, 3)? as u8),
+            ("SAVE", 2) => Op::SaveRange(self.register(&ops[0])?, self.register(&ops[1])?),
+            ("LOAD", 2) => Op::LoadRange(self.register(&ops[0])?, self.register(&ops[1])?),
+            ("LD", 2) => self.load(&ops[0], &ops[1])?,
+            _ => {
+                if MNEMONICS.contains(&name.as_str()) {
+                    return Err(mnemonic.error(format!("wrong number of operands for {}", name)));
+                }
+                return Err(mnemonic.error(format!("unknown instruction {}", mnemonic.text)));
+            },
+        };
+        return Ok(op);
+    }
+
+    fn load(&self, dst: &Token, src: &Token) -> Result<Op, AsmError> {
+        let op = match dst.keyword().as_str() {
+            "I" if is_long(src) => Op::LdILong,
+            "I" => Op::LdI(self.addr(src)?),
+            "DT" => Op::LdDtVx(self.register(src)?),
+            "ST" => Op::LdStVx(self.register(src)?),
+            "F" => Op::LdF(self.register(src)?),
+            "HF" => Op::LdHf(self.register(src)?),
+            "B" => Op::LdB(self.register(src)?),
+            "[I]" => Op::LdIVx(self.register(src)?),
+            "R" => Op::LdRVx(self.register(src)?),
+            "PITCH" => Op::Pitch(self.register(src)?),
+            _ => {
+                let x = self.register(dst)?;
+                match src.keyword().as_str() {
+                    "DT" => Op::LdVxDt(x),
+                    "K" => Op::LdVxK(x),
+                    "[I]" => Op::LdVxI(x),
+                    "R" => Op::LdVxR(x),
+                    _ => match parse_register(&src.text) {
+                        Some(y) => Op::LdReg(x, y),
+                        None => Op::LdByte(x, self.byte(src)?),
+                    },
+                }
+            },
+        };
+        return Ok(op);
+    }
+
+    fn register(&self, token: &Token) -> Result<u8, AsmError> {
+        return parse_register(&token.text)
+            .ok_or_else(|| token.error(format!("expected a register, found {}", token.text)));
+    }
+
+    fn addr(&self, token: &Token) -> Result<u16, AsmError> {
+        return Ok(self.value(token, 0, 0xFFF)? as u16);
+    }
+
+    /// Bytes may also be written as negative numbers
+    fn byte(&self, token: &Token) -> Result<u8, AsmError> {
+        return Ok(self.value(token, -128, 0xFF)? as u8);
+    }
+
+    fn value(&self, token: &Token, min: i64, max: i64) -> Result<i64, AsmError> {
+        let value = self.eval(token, 0)?;
+        if value < min || value > max {
+            return Err(token.error(format!("{} is out of range, expected {} to {:#X}", value, min, max)));
+        }
+        return Ok(value);
+    }
+
+    /// Evaluate a sum of numbers, labels and constants like `data + 2`
+    fn eval(&self, token: &Token, depth: usize) -> Result<i64, AsmError> {
+        if depth > self.constants.len() {
+            return Err(token.error(format!("constant {} is defined in terms of itself", token.text)));
+        }
+
+        let mut total = 0;
+        let mut sign = 1;
+        let mut expect_term = true;
+        let mut rest = token.text.as_str();
+        let mut column = token.column;
+        while !rest.is_empty() {
+            let trimmed = rest.trim_start();
+            column += rest.len() - trimmed.len();
+            rest = trimmed;
+            if rest.is_empty() {
+                break;
+            }
+
+            let at = Token { text: rest.to_string(), column, ..token.clone() };
+            if expect_term && rest.starts_with('-') {
+                sign = -sign;
+                rest = &rest[1..];
+                column += 1;
+                continue;
+            }
+            if !expect_term {
+                sign = match rest.as_bytes()[0] {
+                    b'+' => 1,
+                    b'-' => -1,
+                    _ => return Err(at.error(format!("expected + or -, found {}", rest))),
+                };
+                rest = &rest[1..];
+                column += 1;
+                expect_term = true;
+                continue;
+            }
+
+            let len = rest.find(|c: char| c.is_whitespace() || c == '+' || c == '-').unwrap_or(rest.len());
+            let term = Token { text: rest[..len].to_string(), ..at };
+            total += sign * self.term(&term, depth)?;
+            sign = 1;
+            expect_term = false;
+            rest = &rest[len..];
+            column += len;
+        }
+
+        if expect_term {
+            return Err(token.error(String::from("expected a value")));
+        }
+        return Ok(total);
+    }
+
+    fn term(&self, token: &Token, depth: usize) -> Result<i64, AsmError> {
+        if let Some(value) = parse_number(&token.text) {
+            return Ok(value);
+        }
+        if let Some((addr, _)) = self.labels.get(&token.text) {
+            return Ok(*addr as i64);
+        }
+        if let Some(value) = self.constants.get(&token.text) {
+            return self.eval(value, depth + 1);
+        }
+        if token.text.starts_with(|c: char| c.is_ascii_digit() || c == '$') {
+            return Err(token.error(format!("invalid number {}", token.text)));
+        }
+        return Err(token.error(format!("undefined symbol {}", token.text)));
+    }
+}
+
+/// Size in bytes of one `db` operand, strings must be quoted at both ends
+fn data_len(token: &Token) -> Result<usize, AsmError> {
+    let text = &token.text;
+    if !text.starts_with('"') && !text.ends_with('"') {
+        return Ok(1);
+    }
+    if text.len() < 2 || !text.starts_with('"') || !text.ends_with('"') {
+        return Err(token.error(format!("unterminated string {}", text)));
+    }
+    return Ok(text.len() - 2);
+}
+
+fn is_long(token: &Token) -> bool {
+    let text = token.keyword();
+    return text.starts_with("LONG ") || text.starts_with("LONG\t");
+}
+
+fn parse_register(text: &str) -> Option<u8> {
+    if text.len() == 2 && (text.starts_with('V') || text.starts_with('v')) {
+        return u8::from_str_radix(&text[1..], 16).ok();
+    }
+    return None;
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let (digits, radix) = if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        (hex, 16)
+    } else if let Some(binary) = text.strip_prefix("0b").or_else(|| text.strip_prefix("0B")) {
+        (binary, 2)
+    } else if let Some(hex) = text.strip_prefix('$') {
+        (hex, 16)
+    } else {
+        (text, 10)
+    };
+    return i64::from_str_radix(digits, radix).ok();
+}
+
+/// Split a line into its first word (a label or mnemonic), the next word and
+/// the comma separated operands, dropping any comment
+fn split_line(text: &str, file: &str, line: usize) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let token = |start: usize, end: usize| Token {
+        text: text[start..end].trim_end().to_string(),
+        file: file.to_string(),
+        line,
+        column: start + 1,
+    };
+
+    let mut in_string = false;
+    let mut end = text.len();
+    for (i, c) in text.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            ';' if !in_string => {
+                end = i;
+                break;
+            },
+            _ => {},
+        }
+    }
+    let text_end = end;
+
+    // Leading words are separated by whitespace, as a label and mnemonic can
+    // share a line
+    let mut pos = 0;
+    loop {
+        let start = match text[pos..text_end].find(|c: char| !c.is_whitespace()) {
+            Some(offset) => pos + offset,
+            None => return tokens,
+        };
+        let word_end = text[start..text_end].find(char::is_whitespace).map(|o| start + o).unwrap_or(text_end);
+        let word = &text[start..word_end];
+        let is_label = word.ends_with(':') && tokens.is_empty();
+        let is_constant = tokens.len() == 1 && word.eq_ignore_ascii_case("equ");
+        tokens.push(token(start, word_end));
+        pos = word_end;
+        if !(is_label || is_constant || tokens.len() == 1 && next_is_equ(&text[pos..text_end])) {
+            break;
+        }
+    }
+
+    // Everything after the mnemonic is comma separated operands
+    let mut start = pos;
+    let mut in_string = false;
+    for (i, c) in text[pos..text_end].char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            ',' if !in_string => {
+                push_operand(&mut tokens, token, start, pos + i);
+                start = pos + i + 1;
+            },
+            _ => {},
+        }
+    }
+    push_operand(&mut tokens, token, start, text_end);
+    return tokens;
+}
+
+fn next_is_equ(rest: &str) -> bool {
+    return rest.split_whitespace().next().map(|w| w.eq_ignore_ascii_case("equ")).unwrap_or(false);
+}
+
+fn push_operand(tokens: &mut Vec<Token>, token: impl Fn(usize, usize) -> Token, start: usize, end: usize) {
+    let mut operand = token(start, end);
+    let trimmed = operand.text.trim_start().to_string();
+    operand.column += operand.text.len() - trimmed.len();
+    operand.text = trimmed;
+    if !operand.text.is_empty() {
+        tokens.push(operand);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::{disassemble, Syntax};
+
+    fn error_at(source: &str) -> (usize, usize, String) {
+        let e = assemble(source).unwrap_err();
+        return (e.line, e.column, e.message);
+    }
+
+    #[test]
+    fn test_instructions() {
+        let source = "\
+            CLS\n\
+            LD V1, 0x05\n\
+            ld v2, v1\n\
+            LD I, 0x300\n\
+            DRW V1, V2, 5\n\
+            SHR V3\n\
+            LD [I], V2\n\
+            ADD I, VA\n\
+            JP V0, 0x200\n";
+        assert_eq!(
+            assemble(source).unwrap(),
+            vec![0x00, 0xE0, 0x61, 0x05, 0x82, 0x10, 0xA3, 0x00, 0xD1, 0x25,
+                 0x83, 0x36, 0xF2, 0x55, 0xFA, 0x1E, 0xB2, 0x00],
+        );
+    }
+
+    #[test]
+    fn test_labels_and_constants() {
+        let source = "\
+            SPEED equ 3\n\
+            start:\n\
+            \tADD V0, SPEED ; move\n\
+            \tCALL draw\n\
+            \tJP start\n\
+            draw: LD I, sprite + 1\n\
+            \tRET\n\
+            sprite: db 0xFF, 0b1010, -1\n\
+            dw 0x1234\n";
+        assert_eq!(
+            assemble(source).unwrap(),
+            vec![0x70, 0x03, 0x22, 0x06, 0x12, 0x00, 0xA2, 0x0B, 0x00, 0xEE,
+                 0xFF, 0x0A, 0xFF, 0x12, 0x34],
+        );
+    }
+
+    #[test]
+    fn test_long_load() {
+        let source = "LD I, long data\nEXIT\ndata: db \"AB\"\n";
+        assert_eq!(assemble(source).unwrap(), vec![0xF0, 0x00, 0x02, 0x06, 0x00, 0xFD, 0x41, 0x42]);
+    }
+
+    #[test]
+    fn test_include() {
+        let dir = std::env::temp_dir().join(format!("chip8-asm-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("main.c8asm"), "include \"sprites.c8asm\"\nLD I, ball\n").unwrap();
+        fs::write(dir.join("sprites.c8asm"), "ball: db 0x80\n").unwrap();
+        let rom = assemble_file(&dir.join("main.c8asm"));
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(rom.unwrap(), vec![0x80, 0xA2, 0x00]);
+    }
+
+    #[test]
+    fn test_error_positions() {
+        assert_eq!(error_at("CLS\n  FOO V1"), (2, 3, String::from("unknown instruction FOO")));
+        assert_eq!(error_at("LD V1, nowhere"), (1, 8, String::from("undefined symbol nowhere")));
+        assert_eq!(error_at("LD I, 0x200 + 0x1000"), (1, 7, String::from("4608 is out of range, expected 0 to 0xFFF")));
+        assert_eq!(error_at("OR V1,  VZ"), (1, 9, String::from("expected a register, found VZ")));
+        assert_eq!(error_at("DRW V1, V2"), (1, 1, String::from("wrong number of operands for DRW")));
+        assert_eq!(error_at("a:\na:"), (2, 1, String::from("a is already defined at <input>:1")));
+        assert_eq!(error_at("LD V1, 12z"), (1, 8, String::from("invalid number 12z")));
+    }
+
+    #[test]
+    fn test_unterminated_strings() {
+        assert_eq!(error_at("db \""), (1, 4, String::from("unterminated string \"")));
+        assert_eq!(error_at("db 1, \"abc"), (1, 7, String::from("unterminated string \"abc")));
+        assert_eq!(error_at("db abc\""), (1, 4, String::from("unterminated string abc\"")));
+        assert_eq!(assemble("db \"\", \"ab\"").unwrap(), b"ab".to_vec());
+    }
+
+    #[test]
+    fn test_programs_round_trip() {
+        let roms: [&[u8]; 6] = [
+            include_bytes!("../programs/PONG"),
+            include_bytes!("../programs/INVADERS"),
+            include_bytes!("../programs/test_opcode.ch8"),
+            include_bytes!("../programs/BC_test.ch8"),
+            include_bytes!("../programs/c8_test.c8"),
+            include_bytes!("../programs/Maze (alt) [David Winter, 199x].ch8"),
+        ];
+        for rom in roms.iter() {
+            let listing = disassemble(rom, Syntax::Cowgod);
+            assert_eq!(assemble(&listing).unwrap(), rom.to_vec());
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A problem reading the config file, `line` starts at 1
+#[derive(Debug)]
+pub struct ConfigError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "line {}: {}", self.line, self.message);
+    }
+}
+
+impl Error for ConfigError {}
+
+/// Settings from an INI style file. Entries before any section apply to
+/// every ROM, and a `[NAME]` section overrides them for the ROM file called
+/// NAME:
+///
+/// ```text
+/// ; the default for every ROM
+/// keymap = qwerty
+///
+/// [INVADERS]
+/// key_5 = space
+/// ```
+///
+/// Comments start with `;`, or `#` at the start of a line. The text is kept
+/// line by line so `set` can change a value without losing the comments
+/// around it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Config {
+    lines: Vec<String>,
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        let config = Config { lines: text.lines().map(String::from).collect() };
+        for (i, line) in config.lines.iter().enumerate() {
+            if let Line::Invalid = parse_line(line) {
+                return Err(ConfigError { line: i + 1, message: format!("expected [ROM] or key = value, found {}", line.trim()) });
+            }
+        }
+        return Ok(config);
+    }
+
+    /// A missing file is an empty config
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        return match fs::read_to_string(path) {
+            Ok(text) => Ok(Config::parse(&text)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(Box::new(e)),
+        };
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        return fs::write(path, self.to_string());
+    }
+
+    /// `~/.config/chip8-rs/config.ini`, or under `$XDG_CONFIG_HOME`
+    pub fn default_path() -> Option<PathBuf> {
+        let base = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+        return Some(base.join("chip8-rs").join("config.ini"));
+    }
+
+    /// The section for a ROM, its file name without the extension
+    pub fn section_for(rom: &str) -> String {
+        return Path::new(rom).file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    }
+
+    /// The value of `key` for `rom`, falling back to the global entry
+    pub fn get(&self, rom: &str, key: &str) -> Option<&str> {
+        return self.get_in(Some(rom), key).or_else(|| self.get_in(None, key));
+    }
+
+    /// The value of `key` in one section, None being the global entries
+    pub fn get_in(&self, section: Option<&str>, key: &str) -> Option<&str> {
+        let mut current = None;
+        let mut found = None;
+        for line in self.lines.iter() {
+            match parse_line(line) {
+                Line::Section(name) => current = Some(name),
+                Line::Entry(k, value) if current == section && k.eq_ignore_ascii_case(key) => found = Some(value),
+                _ => {},
+            }
+        }
+        return found;
+    }
+
+    /// Set `key` in `section`, replacing an existing entry in place or adding
+    /// one at the end of the section
+    pub fn set(&mut self, section: Option<&str>, key: &str, value: &str) {
+        let entry = format!("{} = {}", key, value);
+        let mut current = None;
+        let mut last_in_section = if section.is_none() { Some(0) } else { None };
+        let mut existing = None;
+        for (i, line) in self.lines.iter().enumerate() {
+            match parse_line(line) {
+                Line::Section(name) => current = Some(name),
+                Line::Entry(k, _) if current == section => {
+                    last_in_section = Some(i + 1);
+                    if k.eq_ignore_ascii_case(key) {
+                        existing = Some(i);
+                    }
+                },
+                _ if current == section && section.is_some() && last_in_section.is_none() => {
+                    // The section header itself
+                    last_in_section = Some(i + 1);
+                },
+                _ => {},
+            }
+        }
+
+        match (existing, last_in_section, section) {
+            (Some(i), _, _) => self.lines[i] = entry,
+            (None, Some(i), _) => self.lines.insert(i, entry),
+            (None, None, Some(name)) => {
+                if self.lines.last().map(|l| !l.trim().is_empty()).unwrap_or(false) {
+                    self.lines.push(String::new());
+                }
+                self.lines.push(format!("[{}]", name));
+                self.lines.push(entry);
+            },
+            (None, None, None) => unreachable!(),
+        }
+    }
+}
+
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in self.lines.iter() {
+            writeln!(f, "{}", line)?;
+        }
+        return Ok(());
+    }
+}
+
+enum Line<'a> {
+    Blank,
+    Section(&'a str),
+    Entry(&'a str, &'a str),
+    Invalid,
+}
+
+fn parse_line(line: &str) -> Line<'_> {
+    // `#` only starts a comment at the beginning of a line, so colours like
+    // #FFB000 can be written as values
+    let line = match line.find(';') {
+        Some(i) => &line[..i],
+        None => line,
+    }.trim();
+
+    if line.is_empty() || line.starts_with('#') {
+        return Line::Blank;
+    }
+    if line.starts_with('[') && line.ends_with(']') {
+        return Line::Section(line[1..line.len() - 1].trim());
+    }
+    return match line.find('=') {
+        Some(i) if i > 0 => Line::Entry(line[..i].trim(), line[i + 1..].trim()),
+        _ => Line::Invalid,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "\
+; settings
+# for every ROM
+keymap = qwerty
+fg = #FFB000
+
+[INVADERS]
+key_5 = Space ; fire
+keymap = literal
+";
+
+    #[test]
+    fn test_get() {
+        let config = Config::parse(TEXT).unwrap();
+        assert_eq!(config.get("PONG", "keymap"), Some("qwerty"));
+        assert_eq!(config.get("PONG", "fg"), Some("#FFB000"));
+        assert_eq!(config.get("INVADERS", "keymap"), Some("literal"));
+        assert_eq!(config.get("INVADERS", "KEY_5"), Some("Space"));
+        assert_eq!(config.get("PONG", "key_5"), None);
+        assert_eq!(config.get_in(None, "key_5"), None);
+    }
+
+    #[test]
+    fn test_section_for() {
+        assert_eq!(Config::section_for("programs/INVADERS"), "INVADERS");
+        assert_eq!(Config::section_for("roms/pong.ch8"), "pong");
+    }
+
+    #[test]
+    fn test_invalid() {
+        let e = Config::parse("keymap = qwerty\nnonsense\n").unwrap_err();
+        assert_eq!(e.line, 2);
+    }
+
+    #[test]
+    fn test_set_existing_keeps_comments() {
+        let mut config = Config::parse(TEXT).unwrap();
+        config.set(Some("INVADERS"), "key_5", "X");
+        assert_eq!(config.get("INVADERS", "key_5"), Some("X"));
+        assert!(config.to_string().starts_with("; settings\n"));
+        assert_eq!(config.to_string().lines().count(), TEXT.lines().count());
+    }
+
+    #[test]
+    fn test_set_new_entries() {
+        let mut config = Config::parse(TEXT).unwrap();
+        config.set(Some("INVADERS"), "key_4", "Left");
+        config.set(None, "palette", "amber");
+        config.set(Some("PONG"), "key_1", "W");
+        assert_eq!(config.get("INVADERS", "key_4"), Some("Left"));
+        assert_eq!(config.get("PONG", "palette"), Some("amber"));
+        assert_eq!(config.get("PONG", "key_1"), Some("W"));
+        assert_eq!(config.get("INVADERS", "key_1"), None);
+
+        let reparsed = Config::parse(&config.to_string()).unwrap();
+        assert_eq!(reparsed, config);
+    }
+
+    #[test]
+    fn test_set_in_empty() {
+        let mut config = Config::default();
+        config.set(Some("PONG"), "key_1", "W");
+        assert_eq!(config.to_string(), "[PONG]\nkey_1 = W\n");
+    }
+}
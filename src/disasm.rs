@@ -0,0 +1,473 @@
+use std::collections::BTreeMap;
+use crate::hardware::instruction::{Instruction, Op, OPCODE_LEN};
+
+/// Address programs are loaded at
+pub const START: u16 = 0x200;
+
+const DATA_PER_LINE: usize = 8;
+
+pub const SYNTAXES: [&str; 2] = ["cowgod", "octo"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Syntax {
+    /// Mnemonics from Cowgod's Chip-8 technical reference, e.g. `DRW V1, V2, 5`
+    Cowgod,
+    /// Octo assembly, e.g. `sprite v1 v2 5`
+    Octo,
+}
+
+impl Syntax {
+    pub fn from_name(name: &str) -> Option<Syntax> {
+        return match name {
+            "cowgod" => Some(Syntax::Cowgod),
+            "octo" => Some(Syntax::Octo),
+            _ => None,
+        };
+    }
+
+    fn comment(&self) -> &'static str {
+        return match self {
+            Syntax::Cowgod => ";",
+            Syntax::Octo => "#",
+        };
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Data,
+    // First byte of an instruction
+    Code,
+    // Remaining bytes of an instruction
+    Operand,
+}
+
+// Ordered so a call target wins over a jump target wins over data
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum LabelKind {
+    Data,
+    Jump,
+    Sub,
+}
+
+/// Turn a ROM into an assembly listing. Code is found by following every
+/// path through the program from `START`, anything never reached is data.
+pub fn disassemble(rom: &[u8], syntax: Syntax) -> String {
+    return Disassembly::analyse(rom).listing(syntax);
+}
+
+struct Disassembly<'a> {
+    rom: &'a [u8],
+    kinds: Vec<Kind>,
+    labels: BTreeMap<u16, String>,
+}
+
+impl<'a> Disassembly<'a> {
+    fn analyse(rom: &'a [u8]) -> Self {
+        let mut kinds = vec![Kind::Data; rom.len()];
+        let mut targets: BTreeMap<u16, LabelKind> = BTreeMap::new();
+        let mut add_target = |addr: u16, kind: LabelKind| {
+            let entry = targets.entry(addr).or_insert(kind);
+            *entry = (*entry).max(kind);
+        };
+
+        let mut work = vec![START];
+        while let Some(mut pc) = work.pop() {
+            while let Some(op) = decode_at(rom, pc) {
+                let offset = (pc - START) as usize;
+                let len = op.len() as usize;
+                // This interpreter can't run machine code routines either
+                if let Op::Unknown(_) | Op::Sys(_) = op {
+                    break;
+                }
+                if offset + len > rom.len() || kinds[offset..offset + len].iter().any(|k| *k != Kind::Data) {
+                    break;
+                }
+
+                kinds[offset] = Kind::Code;
+                for kind in kinds[offset + 1..offset + len].iter_mut() {
+                    *kind = Kind::Operand;
+                }
+
+                match op {
+                    Op::Jp(addr) | Op::JpV0(addr) => {
+                        add_target(addr, LabelKind::Jump);
+                        work.push(addr);
+                        break;
+                    },
+                    Op::Call(addr) => {
+                        add_target(addr, LabelKind::Sub);
+                        work.push(addr);
+                    },
+                    Op::Ret | Op::Exit => break,
+                    Op::LdI(addr) => add_target(addr, LabelKind::Data),
+                    Op::LdILong => add_target(read_word(rom, pc.wrapping_add(OPCODE_LEN)).unwrap(), LabelKind::Data),
+                    _ => {},
+                }
+
+                pc = pc.wrapping_add(len as u16);
+                if op.is_skip() {
+                    let skipped = match decode_at(rom, pc) {
+                        Some(Op::LdILong) => OPCODE_LEN * 2,
+                        _ => OPCODE_LEN,
+                    };
+                    work.push(pc.wrapping_add(skipped));
+                }
+            }
+        }
+
+        // Only label addresses that start a line of the listing
+        let labels = targets.into_iter()
+            .filter(|(addr, _)| {
+                *addr >= START && matches!(kinds.get((addr - START) as usize), Some(Kind::Code) | Some(Kind::Data))
+            })
+            .map(|(addr, kind)| {
+                let prefix = match kind {
+                    LabelKind::Sub => "sub",
+                    LabelKind::Jump => "label",
+                    LabelKind::Data => "data",
+                };
+                (addr, format!("{}_{:03X}", prefix, addr))
+            })
+            .collect();
+
+        return Disassembly { rom, kinds, labels };
+    }
+
+    fn address(&self, addr: u16) -> String {
+        return match self.labels.get(&addr) {
+            Some(label) => label.clone(),
+            None => format!("{:#05X}", addr),
+        };
+    }
+
+    /// The label at `START + offset`. Offsets are kept in usize as data can
+    /// run past the top of a 16 bit address.
+    fn label_at(&self, offset: usize) -> Option<&String> {
+        let addr = START as usize + offset;
+        return if addr <= 0xFFFF { self.labels.get(&(addr as u16)) } else { None };
+    }
+
+    fn listing(&self, syntax: Syntax) -> String {
+        let mut out = String::new();
+        let mut offset = 0;
+        while offset < self.rom.len() {
+            let addr = START as usize + offset;
+            if let Some(label) = self.label_at(offset) {
+                match syntax {
+                    Syntax::Cowgod => out += &format!("{}:\n", label),
+                    Syntax::Octo => out += &format!(": {}\n", label),
+                }
+            }
+
+            if self.kinds[offset] == Kind::Code {
+                // Code was only found at addresses that fit in 16 bits
+                let addr = addr as u16;
+                let op = decode_at(self.rom, addr).unwrap();
+                let len = op.len() as usize;
+                let text = match op {
+                    Op::LdILong => {
+                        let long = self.address(read_word(self.rom, addr.wrapping_add(OPCODE_LEN)).unwrap());
+                        match syntax {
+                            Syntax::Cowgod => format!("LD I, long {}", long),
+                            Syntax::Octo => format!("i := long {}", long),
+                        }
+                    },
+                    _ => match syntax {
+                        Syntax::Cowgod => format_cowgod(&op, &|a| self.address(a)),
+                        Syntax::Octo => format_octo(&op, &|a| self.address(a)),
+                    },
+                };
+                out += &format!("    {:<28}{} {:04X}: {}\n", text, syntax.comment(), addr,
+                    hex_bytes(&self.rom[offset..offset + len]));
+                offset += len;
+            } else {
+                let mut end = offset + 1;
+                while end < self.rom.len() && end - offset < DATA_PER_LINE &&
+                    self.kinds[end] == Kind::Data && self.label_at(end).is_none() {
+                    end += 1;
+                }
+                let bytes: Vec<String> = self.rom[offset..end].iter().map(|b| format!("{:#04X}", b)).collect();
+                let text = match syntax {
+                    Syntax::Cowgod => format!("db {}", bytes.join(", ")),
+                    Syntax::Octo => bytes.join(" "),
+                };
+                out += &format!("    {:<28}{} {:04X}\n", text, syntax.comment(), addr);
+                offset = end;
+            }
+        }
+        return out;
+    }
+}
+
+fn read_word(rom: &[u8], addr: u16) -> Option<u16> {
+    let offset = addr.checked_sub(START)? as usize;
+    if offset + 1 >= rom.len() {
+        return None;
+    }
+    return Some((rom[offset] as u16) << 8 | rom[offset + 1] as u16);
+}
+
+fn decode_at(rom: &[u8], addr: u16) -> Option<Op> {
+    return read_word(rom, addr).map(|opcode| Instruction::new(opcode).decode());
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    return bytes.iter().map(|b| format!("{:02X}", b)).collect();
+}
+
+/// Format an instruction in Cowgod syntax, `addr` renders address operands
+pub fn format_cowgod(op: &Op, addr: &dyn Fn(u16) -> String) -> String {
+    return match *op {
+        Op::Sys(a) => format!("SYS {}", addr(a)),
+        Op::Cls => String::from("CLS"),
+        Op::Ret => String::from("RET"),
+        Op::Jp(a) => format!("JP {}", addr(a)),
+        Op::Call(a) => format!("CALL {}", addr(a)),
+        Op::SeByte(x, kk) => format!("SE V{:X}, {:#04X}", x, kk),
+        Op::SneByte(x, kk) => format!("SNE V{:X}, {:#04X}", x, kk),
+        Op::SeReg(x, y) => format!("SE V{:X}, V{:X}", x, y),
+        Op::LdByte(x, kk) => format!("LD V{:X}, {:#04X}", x, kk),
+        Op::AddByte(x, kk) => format!("ADD V{:X}, {:#04X}", x, kk),
+        Op::LdReg(x, y) => format!("LD V{:X}, V{:X}", x, y),
+        Op::Or(x, y) => format!("OR V{:X}, V{:X}", x, y),
+        Op::And(x, y) => format!("AND V{:X}, V{:X}", x, y),
+        Op::Xor(x, y) => format!("XOR V{:X}, V{:X}", x, y),
+        Op::AddReg(x, y) => format!("ADD V{:X}, V{:X}", x, y),
+        Op::Sub(x, y) => format!("SUB V{:X}, V{:X}", x, y),
+        Op::Shr(x, y) => format!("SHR V{:X}, V{:X}", x, y),
+        Op::Subn(x, y) => format!("SUBN V{:X}, V{:X}", x, y),
+        Op::Shl(x, y) => format!("SHL V{:X}, V{:X}", x, y),
+        Op::SneReg(x, y) => format!("SNE V{:X}, V{:X}", x, y),
+        Op::LdI(a) => format!("LD I, {}", addr(a)),
+        Op::JpV0(a) => format!("JP V0, {}", addr(a)),
+        Op::Rnd(x, kk) => format!("RND V{:X}, {:#04X}", x, kk),
+        Op::Drw(x, y, n) => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        Op::Skp(x) => format!("SKP V{:X}", x),
+        Op::Sknp(x) => format!("SKNP V{:X}", x),
+        Op::LdVxDt(x) => format!("LD V{:X}, DT", x),
+        Op::LdVxK(x) => format!("LD V{:X}, K", x),
+        Op::LdDtVx(x) => format!("LD DT, V{:X}", x),
+        Op::LdStVx(x) => format!("LD ST, V{:X}", x),
+        Op::AddI(x) => format!("ADD I, V{:X}", x),
+        Op::LdF(x) => format!("LD F, V{:X}", x),
+        Op::LdB(x) => format!("LD B, V{:X}", x),
+        Op::LdIVx(x) => format!("LD [I], V{:X}", x),
+        Op::LdVxI(x) => format!("LD V{:X}, [I]", x),
+        Op::Scd(n) => format!("SCD {}", n),
+        Op::Scr => String::from("SCR"),
+        Op::Scl => String::from("SCL"),
+        Op::Exit => String::from("EXIT"),
+        Op::Low => String::from("LOW"),
+        Op::High => String::from("HIGH"),
+        Op::LdHf(x) => format!("LD HF, V{:X}", x),
+        Op::LdRVx(x) => format!("LD R, V{:X}", x),
+        Op::LdVxR(x) => format!("LD V{:X}, R", x),
+        Op::Scu(n) => format!("SCU {}", n),
+        Op::SaveRange(x, y) => format!("SAVE V{:X}, V{:X}", x, y),
+        Op::LoadRange(x, y) => format!("LOAD V{:X}, V{:X}", x, y),
+        Op::LdILong => String::from("LD I, long"),
+        Op::Plane(n) => format!("PLANE {}", n),
+        Op::Audio => String::from("AUDIO"),
+        Op::Pitch(x) => format!("LD PITCH, V{:X}", x),
+        Op::Unknown(opcode) => format!("db {:#04X}, {:#04X}", opcode >> 8, opcode & 0xFF),
+    };
+}
+
+/// Format an instruction in Octo syntax, `addr` renders address operands
+pub fn format_octo(op: &Op, addr: &dyn Fn(u16) -> String) -> String {
+    return match *op {
+        Op::Cls => String::from("clear"),
+        Op::Ret => String::from("return"),
+        Op::Jp(a) => format!("jump {}", addr(a)),
+        Op::Call(a) => format!(":call {}", addr(a)),
+        // Octo conditions say when the next instruction runs, the opposite of
+        // when it's skipped
+        Op::SeByte(x, kk) => format!("if v{:x} != {:#04X} then", x, kk),
+        Op::SneByte(x, kk) => format!("if v{:x} == {:#04X} then", x, kk),
+        Op::SeReg(x, y) => format!("if v{:x} != v{:x} then", x, y),
+        Op::SneReg(x, y) => format!("if v{:x} == v{:x} then", x, y),
+        Op::Skp(x) => format!("if v{:x} -key then", x),
+        Op::Sknp(x) => format!("if v{:x} key then", x),
+        Op::LdByte(x, kk) => format!("v{:x} := {:#04X}", x, kk),
+        Op::AddByte(x, kk) => format!("v{:x} += {:#04X}", x, kk),
+        Op::LdReg(x, y) => format!("v{:x} := v{:x}", x, y),
+        Op::Or(x, y) => format!("v{:x} |= v{:x}", x, y),
+        Op::And(x, y) => format!("v{:x} &= v{:x}", x, y),
+        Op::Xor(x, y) => format!("v{:x} ^= v{:x}", x, y),
+        Op::AddReg(x, y) => format!("v{:x} += v{:x}", x, y),
+        Op::Sub(x, y) => format!("v{:x} -= v{:x}", x, y),
+        Op::Shr(x, y) => format!("v{:x} >>= v{:x}", x, y),
+        Op::Subn(x, y) => format!("v{:x} =- v{:x}", x, y),
+        Op::Shl(x, y) => format!("v{:x} <<= v{:x}", x, y),
+        Op::LdI(a) => format!("i := {}", addr(a)),
+        Op::JpV0(a) => format!("jump0 {}", addr(a)),
+        Op::Rnd(x, kk) => format!("v{:x} := random {:#04X}", x, kk),
+        Op::Drw(x, y, n) => format!("sprite v{:x} v{:x} {}", x, y, n),
+        Op::LdVxDt(x) => format!("v{:x} := delay", x),
+        Op::LdVxK(x) => format!("v{:x} := key", x),
+        Op::LdDtVx(x) => format!("delay := v{:x}", x),
+        Op::LdStVx(x) => format!("buzzer := v{:x}", x),
+        Op::AddI(x) => format!("i += v{:x}", x),
+        Op::LdF(x) => format!("i := hex v{:x}", x),
+        Op::LdB(x) => format!("bcd v{:x}", x),
+        Op::LdIVx(x) => format!("save v{:x}", x),
+        Op::LdVxI(x) => format!("load v{:x}", x),
+        Op::Scd(n) => format!("scroll-down {}", n),
+        Op::Scr => String::from("scroll-right"),
+        Op::Scl => String::from("scroll-left"),
+        Op::Exit => String::from("exit"),
+        Op::Low => String::from("lores"),
+        Op::High => String::from("hires"),
+        Op::LdHf(x) => format!("i := bighex v{:x}", x),
+        Op::LdRVx(x) => format!("saveflags v{:x}", x),
+        Op::LdVxR(x) => format!("loadflags v{:x}", x),
+        Op::Scu(n) => format!("scroll-up {}", n),
+        Op::SaveRange(x, y) => format!("save v{:x} - v{:x}", x, y),
+        Op::LoadRange(x, y) => format!("load v{:x} - v{:x}", x, y),
+        // The address is in the word after, which Octo only accepts with
+        // `long`, so emit the raw bytes and let the word follow as data
+        Op::LdILong => String::from("0xF0 0x00"),
+        Op::Plane(n) => format!("plane {}", n),
+        Op::Audio => String::from("audio"),
+        Op::Pitch(x) => format!("pitch := v{:x}", x),
+        // Octo has no machine code call, emit the raw bytes
+        Op::Sys(_) | Op::Unknown(_) => {
+            let opcode = op.encode();
+            format!("{:#04X} {:#04X}", opcode >> 8, opcode & 0xFF)
+        },
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(listing: &str) -> Vec<String> {
+        // Drop the address comments
+        return listing.lines()
+            .map(|l| l.split([';', '#']).next().unwrap().trim().to_string())
+            .collect();
+    }
+
+    #[test]
+    fn test_straight_line() {
+        let rom = [0x60, 0x05, 0x00, 0xE0, 0x00, 0xFD];
+        assert_eq!(lines(&disassemble(&rom, Syntax::Cowgod)), ["LD V0, 0x05", "CLS", "EXIT"]);
+    }
+
+    #[test]
+    fn test_labels() {
+        let rom = [
+            0x22, 0x06, // CALL sub_206
+            0xA2, 0x08, // LD I, data_208
+            0x12, 0x04, // JP label_204
+            0x00, 0xEE, // RET
+            0xFF, 0x81,
+        ];
+        assert_eq!(lines(&disassemble(&rom, Syntax::Cowgod)), [
+            "CALL sub_206",
+            "LD I, data_208",
+            "label_204:",
+            "JP label_204",
+            "sub_206:",
+            "RET",
+            "data_208:",
+            "db 0xFF, 0x81",
+        ]);
+    }
+
+    #[test]
+    fn test_unreached_is_data() {
+        let rom = [0x12, 0x00, 0x60, 0x05];
+        assert_eq!(lines(&disassemble(&rom, Syntax::Cowgod)), [
+            "label_200:",
+            "JP label_200",
+            "db 0x60, 0x05",
+        ]);
+    }
+
+    #[test]
+    fn test_skip_follows_both_paths() {
+        let rom = [0x30, 0x01, 0x12, 0x06, 0x00, 0xFD, 0x00, 0xE0, 0x00, 0xFD];
+        assert_eq!(lines(&disassemble(&rom, Syntax::Cowgod)), [
+            "SE V0, 0x01",
+            "JP label_206",
+            "EXIT",
+            "label_206:",
+            "CLS",
+            "EXIT",
+        ]);
+    }
+
+    #[test]
+    fn test_long_load() {
+        let rom = [0xF0, 0x00, 0x02, 0x04, 0x00, 0xFD];
+        assert_eq!(lines(&disassemble(&rom, Syntax::Cowgod)), [
+            "LD I, long data_204",
+            "data_204:",
+            "EXIT",
+        ]);
+    }
+
+    #[test]
+    fn test_odd_length() {
+        let rom = [0x00, 0xEE, 0x12];
+        assert_eq!(lines(&disassemble(&rom, Syntax::Cowgod)), ["RET", "db 0x12"]);
+    }
+
+    #[test]
+    fn test_octo() {
+        let rom = [0x22, 0x06, 0x6A, 0x02, 0xDA, 0xB6, 0x40, 0x01, 0x00, 0xEE];
+        assert_eq!(lines(&disassemble(&rom, Syntax::Octo)), [
+            ":call sub_206",
+            "va := 0x02",
+            "sprite va vb 6",
+            ": sub_206",
+            "if v0 == 0x01 then",
+            "return",
+        ]);
+    }
+
+    #[test]
+    fn test_octo_long_load_alone() {
+        assert_eq!(format_octo(&Op::LdILong, &|a| format!("{}", a)), "0xF0 0x00");
+    }
+
+    #[test]
+    fn test_rom_past_16_bit_addresses() {
+        let mut rom = vec![0; 0x10000];
+        rom[0] = 0x00;
+        rom[1] = 0xE0;
+        let listing = disassemble(&rom, Syntax::Cowgod);
+        assert!(listing.lines().last().unwrap().ends_with("; 101FA"));
+    }
+
+    #[test]
+    fn test_programs_cover_every_byte() {
+        let roms: [&[u8]; 3] = [
+            include_bytes!("../programs/PONG"),
+            include_bytes!("../programs/INVADERS"),
+            include_bytes!("../programs/test_opcode.ch8"),
+        ];
+        for rom in roms.iter() {
+            let listing = disassemble(rom, Syntax::Cowgod);
+            assert!(listing.contains("DRW"));
+
+            // Each line's address comment follows on from the bytes of the
+            // line before, up to the end of the ROM
+            let mut next = START as usize;
+            for line in listing.lines().filter(|l| l.contains(';')) {
+                let (text, comment) = line.split_at(line.find(';').unwrap());
+                let mut fields = comment[1..].trim().split(": ");
+                let addr = usize::from_str_radix(fields.next().unwrap(), 16).unwrap();
+                assert_eq!(addr, next, "{}", line);
+                next += match fields.next() {
+                    Some(hex) => hex.len() / 2,
+                    None => text.matches("0x").count(),
+                };
+            }
+            assert_eq!(next, START as usize + rom.len());
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use crate::hardware::chip8::Chip8;
+
+/// Names accepted by `Mode::from_name`, in the order a hotkey cycles them
+pub const FILTERS: [&str; 4] = ["none", "max", "phosphor", "clear"];
+
+/// How long a pixel takes to fade out with the phosphor filter
+pub const DEFAULT_DECAY_MS: u32 = 150;
+
+// Frames a freshly cleared screen is held back before it's shown anyway, so
+// ROMs that really want a blank screen still get one
+const MAX_HOLD: u32 = 3;
+
+const FRAME_MS: f32 = 1000.0 / 60.0;
+
+/// Ways of hiding the flicker from ROMs erasing and redrawing sprites
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Show the screen as it is
+    None,
+    /// Show a pixel if it was lit in either of the last two frames
+    Max,
+    /// Pixels fade out over the decay time instead of going dark at once
+    Phosphor,
+    /// Keep showing the last picture while the screen is cleared and not yet
+    /// drawn on again
+    DrawAfterClear,
+}
+
+impl Mode {
+    pub fn from_name(name: &str) -> Option<Mode> {
+        return match name.to_ascii_lowercase().as_str() {
+            "none" => Some(Mode::None),
+            "max" => Some(Mode::Max),
+            "phosphor" => Some(Mode::Phosphor),
+            "clear" => Some(Mode::DrawAfterClear),
+            _ => None,
+        };
+    }
+
+    pub fn name(self) -> &'static str {
+        return match self {
+            Mode::None => FILTERS[0],
+            Mode::Max => FILTERS[1],
+            Mode::Phosphor => FILTERS[2],
+            Mode::DrawAfterClear => FILTERS[3],
+        };
+    }
+
+    /// The filter after this one in `FILTERS`, wrapping round
+    pub fn next(self) -> Mode {
+        let i = FILTERS.iter().position(|name| *name == self.name()).unwrap();
+        return Mode::from_name(FILTERS[(i + 1) % FILTERS.len()]).unwrap();
+    }
+}
+
+/// A picture ready to show
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub width: usize,
+    pub height: usize,
+    /// The colour of each pixel, as in `Chip8::screen`
+    pub pixels: Vec<u8>,
+    /// How brightly each pixel shows its colour over the background, 255
+    /// being fully lit
+    pub brightness: Vec<u8>,
+}
+
+impl Frame {
+    /// A fully lit copy of a bitmap
+    pub fn new(bitmap: &[u8], width: usize, height: usize) -> Self {
+        return Frame {
+            width,
+            height,
+            pixels: bitmap.to_vec(),
+            brightness: vec![255; bitmap.len()],
+        };
+    }
+}
+
+/// Turns the screen after each 60 Hz frame into the picture to show
+pub struct Filter {
+    mode: Mode,
+    decay_ms: u32,
+    // The screen at the last call
+    previous: Vec<u8>,
+    // Phosphor glow of each pixel and the colour it's fading from
+    glow: Vec<f32>,
+    colours: Vec<u8>,
+    // Frames held back in a row by DrawAfterClear
+    held: u32,
+    // Whether another call would give a different picture for the same screen
+    changing: bool,
+}
+
+impl Filter {
+    pub fn new(mode: Mode, decay_ms: u32) -> Self {
+        return Filter {
+            mode,
+            decay_ms,
+            previous: Vec::new(),
+            glow: Vec::new(),
+            colours: Vec::new(),
+            held: 0,
+            changing: false,
+        };
+    }
+
+    pub fn mode(&self) -> Mode {
+        return self.mode;
+    }
+
+    /// Switch filter, forgetting the history of the old one
+    pub fn set_mode(&mut self, mode: Mode) {
+        *self = Filter::new(mode, self.decay_ms);
+    }
+
+    /// Whether `next_frame` needs calling after the next 60 Hz frame even if
+    /// the ROM doesn't draw, e.g. while pixels are still fading
+    pub fn changing(&self) -> bool {
+        return self.changing;
+    }
+
+    /// The picture to show after a 60 Hz frame, or None to keep showing the
+    /// last one
+    pub fn next_frame(&mut self, inter: &Chip8) -> Option<Frame> {
+        let screen = &inter.screen;
+        if self.previous.len() != screen.len() {
+            // New resolution, nothing to blend with
+            self.previous = screen.clone();
+            self.glow = vec![0.0; screen.len()];
+            self.colours = vec![0; screen.len()];
+        }
+
+        let mut frame = Frame::new(screen, inter.width(), inter.height());
+        match self.mode {
+            Mode::None => self.changing = false,
+            Mode::Max => {
+                for (pixel, previous) in frame.pixels.iter_mut().zip(self.previous.iter()) {
+                    *pixel |= previous;
+                }
+                self.changing = frame.pixels != *screen;
+            },
+            Mode::Phosphor => {
+                let fade = if self.decay_ms == 0 { 1.0 } else { FRAME_MS / self.decay_ms as f32 };
+                self.changing = false;
+                for (i, pixel) in screen.iter().copied().enumerate() {
+                    if pixel != 0 {
+                        self.glow[i] = 1.0;
+                        self.colours[i] = pixel;
+                    } else {
+                        self.glow[i] = (self.glow[i] - fade).max(0.0);
+                        self.changing |= self.glow[i] > 0.0;
+                    }
+                    frame.pixels[i] = self.colours[i];
+                    frame.brightness[i] = (self.glow[i] * 255.0).round() as u8;
+                }
+            },
+            Mode::DrawAfterClear => {
+                if inter.awaiting_redraw() && self.held < MAX_HOLD {
+                    self.held += 1;
+                    self.changing = true;
+                    return None;
+                }
+                self.held = 0;
+                self.changing = false;
+            },
+        }
+        self.previous.copy_from_slice(screen);
+        return Some(frame);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Draws the 0 glyph at 0,0
+    fn drawn() -> Chip8 {
+        let mut inter = Chip8::default();
+        inter.execute_instruction(0xD005).unwrap();
+        return inter;
+    }
+
+    #[test]
+    fn test_names() {
+        for name in FILTERS.iter() {
+            assert_eq!(Mode::from_name(name).unwrap().name(), *name);
+        }
+        assert_eq!(Mode::None.next(), Mode::Max);
+        assert_eq!(Mode::DrawAfterClear.next(), Mode::None);
+        assert!(Mode::from_name("blur").is_none());
+    }
+
+    #[test]
+    fn test_none() {
+        let inter = drawn();
+        let mut filter = Filter::new(Mode::None, 0);
+        assert_eq!(filter.next_frame(&inter), Some(Frame::new(&inter.screen, 64, 32)));
+        assert!(!filter.changing());
+    }
+
+    #[test]
+    fn test_max_keeps_erased_sprite_one_frame() {
+        let mut inter = drawn();
+        let mut filter = Filter::new(Mode::Max, 0);
+        filter.next_frame(&inter);
+
+        // Erase the sprite, it still shows for one frame
+        inter.execute_instruction(0xD005).unwrap();
+        let frame = filter.next_frame(&inter).unwrap();
+        assert_eq!(frame.pixels[0], 1);
+        assert!(filter.changing());
+
+        let frame = filter.next_frame(&inter).unwrap();
+        assert_eq!(frame.pixels[0], 0);
+        assert!(!filter.changing());
+    }
+
+    #[test]
+    fn test_phosphor_fades() {
+        let mut inter = drawn();
+        let mut filter = Filter::new(Mode::Phosphor, 40);
+        assert_eq!(filter.next_frame(&inter).unwrap().brightness[0], 255);
+
+        inter.execute_instruction(0xD005).unwrap();
+        let mut last = 255;
+        for _ in 0..3 {
+            let frame = filter.next_frame(&inter).unwrap();
+            assert_eq!(frame.pixels[0], 1);
+            assert!(frame.brightness[0] < last);
+            last = frame.brightness[0];
+        }
+        // 40 ms is gone within three frames
+        assert_eq!(last, 0);
+        assert!(!filter.changing());
+    }
+
+    #[test]
+    fn test_draw_after_clear_holds_cleared_screen() {
+        let mut inter = drawn();
+        let mut filter = Filter::new(Mode::DrawAfterClear, 0);
+        assert!(filter.next_frame(&inter).is_some());
+
+        inter.execute_instruction(0x00E0).unwrap();
+        assert!(filter.next_frame(&inter).is_none());
+        assert!(filter.changing());
+
+        inter.execute_instruction(0xD005).unwrap();
+        assert_eq!(filter.next_frame(&inter).unwrap().pixels[0], 1);
+    }
+
+    #[test]
+    fn test_draw_after_clear_gives_up() {
+        let mut inter = drawn();
+        inter.execute_instruction(0x00E0).unwrap();
+        let mut filter = Filter::new(Mode::DrawAfterClear, 0);
+        for _ in 0..MAX_HOLD {
+            assert!(filter.next_frame(&inter).is_none());
+        }
+        assert_eq!(filter.next_frame(&inter).unwrap().pixels[0], 0);
+    }
+}
@@ -0,0 +1,224 @@
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use crate::hardware::error::EmuError;
+
+/// Names accepted by `BusPolicy::from_name`.
+pub const POLICIES: [&str; 3] = ["error", "wrap", "log"];
+
+/// What an instruction reaching past the end of memory does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BusPolicy {
+    /// Stop with `MemoryOutOfBounds` before anything is read or written
+    Error,
+    /// Addresses wrap round to the start of memory, at 4 KiB or 64 KiB
+    Wrap,
+    /// Record the access in `Bus::take_faults` and carry on, reads past the
+    /// end give 0 and writes are dropped
+    Log,
+}
+
+impl BusPolicy {
+    pub fn from_name(name: &str) -> Option<BusPolicy> {
+        return match name.to_ascii_lowercase().as_str() {
+            "error" => Some(BusPolicy::Error),
+            "wrap" => Some(BusPolicy::Wrap),
+            "log" => Some(BusPolicy::Log),
+            _ => None,
+        };
+    }
+
+    pub fn name(self) -> &'static str {
+        return match self {
+            BusPolicy::Error => POLICIES[0],
+            BusPolicy::Wrap => POLICIES[1],
+            BusPolicy::Log => POLICIES[2],
+        };
+    }
+}
+
+impl Default for BusPolicy {
+    fn default() -> Self {
+        return BusPolicy::Error;
+    }
+}
+
+/// Out of range accesses the Log policy let through, for the frontend to
+/// report
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Faults {
+    pub count: u64,
+    /// First address past the end in the most recent one
+    pub last: usize,
+}
+
+impl fmt::Display for Faults {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "ignored {} out of bounds memory access{}, the last at {:04X}",
+            self.count, if self.count == 1 { "" } else { "es" }, self.last);
+    }
+}
+
+/// Memory as instructions see it. Each access is `check`ed once, then read
+/// or written a byte at a time, so out of range addresses follow the policy
+/// instead of panicking.
+///
+/// The bytes can also be used as a slice, for loading ROMs and states where
+/// the range is already known to fit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bus {
+    memory: Vec<u8>,
+    policy: BusPolicy,
+    // Addresses stored to since `clear_written`, for debugger watchpoints
+    written: Vec<usize>,
+    faults: Option<Faults>,
+}
+
+impl Bus {
+    pub fn new(size: usize, policy: BusPolicy) -> Self {
+        return Bus { memory: vec![0; size], policy, written: Vec::new(), faults: None };
+    }
+
+    pub fn policy(&self) -> BusPolicy {
+        return self.policy;
+    }
+
+    pub fn set_policy(&mut self, policy: BusPolicy) {
+        self.policy = policy;
+    }
+
+    /// Called before an instruction touches `len` bytes from `addr`. Fails
+    /// with the Error policy if any of them are past the end, and records
+    /// one fault for the whole access with the Log policy.
+    pub fn check(&mut self, addr: usize, len: usize) -> Result<(), EmuError> {
+        if addr + len <= self.memory.len() {
+            return Ok(());
+        }
+        let last = addr.max(self.memory.len());
+        match self.policy {
+            BusPolicy::Error => return Err(EmuError::MemoryOutOfBounds { addr: last }),
+            BusPolicy::Wrap => {},
+            BusPolicy::Log => {
+                let count = self.faults.map(|f| f.count).unwrap_or(0) + 1;
+                self.faults = Some(Faults { count, last });
+            },
+        }
+        return Ok(());
+    }
+
+    /// Whether `len` bytes from `addr` can be read without a fault
+    pub fn reachable(&self, addr: usize, len: usize) -> bool {
+        return self.policy == BusPolicy::Wrap || addr + len <= self.memory.len();
+    }
+
+    /// The faults recorded since the last call, None if there weren't any
+    pub fn take_faults(&mut self) -> Option<Faults> {
+        return self.faults.take();
+    }
+
+    pub fn read(&self, addr: usize) -> u8 {
+        return self.memory.get(self.resolve(addr)).copied().unwrap_or(0);
+    }
+
+    pub fn write(&mut self, addr: usize, value: u8) {
+        let addr = self.resolve(addr);
+        if let Some(byte) = self.memory.get_mut(addr) {
+            *byte = value;
+            self.written.push(addr);
+        }
+    }
+
+    /// Addresses stored to through `write` since the last `clear_written`,
+    /// even if the values didn't change
+    pub fn written(&self) -> &[usize] {
+        return &self.written;
+    }
+
+    pub fn clear_written(&mut self) {
+        self.written.clear();
+    }
+
+    // Memory is always a power of two, so wrapping drops the high bits
+    fn resolve(&self, addr: usize) -> usize {
+        return match self.policy {
+            BusPolicy::Wrap => addr & (self.memory.len() - 1),
+            _ => addr,
+        };
+    }
+}
+
+impl Deref for Bus {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        return &self.memory;
+    }
+}
+
+impl DerefMut for Bus {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        return &mut self.memory;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names() {
+        for name in POLICIES.iter() {
+            assert_eq!(BusPolicy::from_name(name).unwrap().name(), *name);
+        }
+        assert!(BusPolicy::from_name("panic").is_none());
+    }
+
+    #[test]
+    fn test_error() {
+        let mut bus = Bus::new(4096, BusPolicy::Error);
+        assert!(bus.check(0xFFE, 2).is_ok());
+        assert!(!bus.reachable(0xFFF, 2));
+        assert!(matches!(bus.check(0xFFE, 3), Err(EmuError::MemoryOutOfBounds { addr: 0x1000 })));
+    }
+
+    #[test]
+    fn test_wrap() {
+        let mut bus = Bus::new(4096, BusPolicy::Wrap);
+        assert!(bus.check(0xFFF, 2).is_ok());
+        assert!(bus.reachable(0xFFF, 2));
+        assert_eq!(bus.take_faults(), None);
+        bus.write(0x1001, 7);
+        assert_eq!(bus[1], 7);
+        assert_eq!(bus.read(0x1001), 7);
+
+        let mut bus = Bus::new(0x10000, BusPolicy::Wrap);
+        bus.write(0x10000, 3);
+        assert_eq!(bus[0], 3);
+    }
+
+    #[test]
+    fn test_written() {
+        let mut bus = Bus::new(4096, BusPolicy::Error);
+        assert!(bus.written().is_empty());
+        bus.write(0x302, 0);
+        bus.write(0x300, 0);
+        assert_eq!(bus.written(), &[0x302, 0x300]);
+        bus.clear_written();
+        assert!(bus.written().is_empty());
+    }
+
+    #[test]
+    fn test_log() {
+        let mut bus = Bus::new(4096, BusPolicy::Log);
+        bus[0] = 5;
+        assert_eq!(bus.take_faults(), None);
+        assert!(bus.check(0xFFE, 3).is_ok());
+        assert!(bus.check(0x2000, 1).is_ok());
+        assert_eq!(bus.take_faults(), Some(Faults { count: 2, last: 0x2000 }));
+        assert_eq!(bus.take_faults(), None);
+        bus.write(0x1000, 7);
+        assert_eq!(bus.read(0x1000), 0);
+        assert!(bus.written().is_empty());
+        assert_eq!(bus.read(0), 5);
+        assert_eq!(bus.len(), 4096);
+    }
+}
@@ -1,19 +1,36 @@
 use std::path::Path;
 use std::fs::File;
 use std::io::Read;
+use crate::hardware::bus::{Bus, BusPolicy, Faults};
+use crate::hardware::error::EmuError;
 use crate::hardware::instruction::{Instruction, OPCODE_LEN};
+use crate::hardware::quirks::{IndexIncrement, Quirks, SpriteEdge};
+use crate::hardware::rng::Rng;
+use crate::hardware::savestate::{self, Writer};
 use bit_vec::BitVec;
-use std::thread;
-use std::time::Duration;
 
 const STACK_SIZE: usize = 16;
 const MEM_SIZE: usize = 4096;
+// XO-CHIP extended memory
+const LARGE_MEM_SIZE: usize = 0x10000;
+const PATTERN_SIZE: usize = 16;
 
 pub const WIDTH: u32 = 64;
 pub const HEIGHT: u32 = 32;
 pub const PIXEL_COUNT: usize = (WIDTH * HEIGHT) as usize;
 
+// SUPER-CHIP high resolution mode
+pub const HIRES_WIDTH: u32 = 128;
+pub const HIRES_HEIGHT: u32 = 64;
+pub const HIRES_PIXEL_COUNT: usize = (HIRES_WIDTH * HIRES_HEIGHT) as usize;
+
 const FONT_SIZE: usize = 80;
+const BIG_FONT_ADDR: usize = FONT_SIZE;
+const BIG_FONT_SIZE: usize = 160;
+const RPL_SIZE: usize = 16;
+
+/// Instructions run per 60 Hz frame unless configured otherwise
+pub const DEFAULT_CYCLES_PER_FRAME: u32 = 10;
 
 static FONTSET: [u8; FONT_SIZE] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
@@ -34,6 +51,25 @@ static FONTSET: [u8; FONT_SIZE] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80  // F
 ];
 
+static BIG_FONTSET: [u8; BIG_FONT_SIZE] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0  // F
+];
+
 
 pub fn version() -> &'static str {
     concat!(env!("CARGO_PKG_VERSION_MAJOR"),
@@ -44,69 +80,465 @@ pub fn version() -> &'static str {
     )
 }
 
+/// A keypad key going down or up, as reported by a frontend
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyEvent {
+    Down(u8),
+    Up(u8),
+}
+
+/// Progress of an FX0A that waits for a key to be pressed and released
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum KeyWait {
+    Idle,
+    /// No key was down when FX0A started
+    Waiting,
+    /// This key went down and FX0A finishes once it's up again
+    Held(u8),
+}
+
+/// Progress of a DXYN waiting for the vertical blank
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DisplayWait {
+    Idle,
+    /// The CPU is stalled until the frame ends
+    Waiting,
+    /// The frame ended, so the DXYN can run
+    Ready,
+}
+
 pub struct Chip8 {
-    memory: Vec<u8>,
+    memory: Bus,
     registers: Vec<u8>,
     I: u16,
     pc: u16,
-    pub screen: [u8; PIXEL_COUNT],
+    pub screen: Vec<u8>,
+    hires: bool,
     dt: u8,
     st: u8,
     stack: Vec<u16>,
-    pub keys: [u8; 16],
+    keys: [u8; 16],
+    key_wait: KeyWait,
+    display_wait: DisplayWait,
     // sp: u8,
 
     pub draw: bool,
-    do_sound: bool,
+    // 00E0 ran and no sprite has been drawn since, only used for display
+    // filtering so it isn't saved
+    cleared: bool,
+    cycles_per_frame: u32,
+
+    // SUPER-CHIP persistent user flags
+    rpl: [u8; RPL_SIZE],
+    exited: bool,
+
+    // XO-CHIP bitmask of the planes drawing affects, each screen pixel holds
+    // one bit per plane
+    plane: u8,
+    pattern: [u8; PATTERN_SIZE],
+    pattern_loaded: bool,
+    pitch: u8,
+
+    quirks: Quirks,
+    rng: Rng,
+    // Identifies the loaded program in save states
+    rom_hash: u64,
 }
 
 
 impl Default for Chip8 {
     fn default() -> Self {
+        return Chip8::new(Quirks::default());
+    }
+}
+
+impl Chip8 {
+    pub fn new(quirks: Quirks) -> Self {
         let mut emu = Chip8 {
-            memory: vec![0; MEM_SIZE],
+            memory: Bus::new(if quirks.large_memory { LARGE_MEM_SIZE } else { MEM_SIZE }, BusPolicy::default()),
             registers: vec![0; STACK_SIZE],
             I: 0,
             pc: 0x200,
-            screen: [0; PIXEL_COUNT],
+            screen: vec![0; PIXEL_COUNT],
+            hires: false,
             dt: 0,
             st: 0,
             stack: Vec::new(),
             keys: [0; 16],
+            key_wait: KeyWait::Idle,
+            display_wait: DisplayWait::Idle,
             draw: false,
-            do_sound: false,
+            cleared: false,
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+            rpl: [0; RPL_SIZE],
+            exited: false,
+            plane: 1,
+            pattern: [0; PATTERN_SIZE],
+            pattern_loaded: false,
+            pitch: 64,
+            quirks,
+            rng: Rng::from_entropy(),
+            rom_hash: savestate::rom_hash(&[]),
         };
 
         // load font
-        for i in 0..FONT_SIZE {
-            emu.memory[i] = FONTSET[i];
-        }
+        emu.memory[..FONT_SIZE].copy_from_slice(&FONTSET);
+        emu.memory[BIG_FONT_ADDR..BIG_FONT_ADDR + BIG_FONT_SIZE].copy_from_slice(&BIG_FONTSET);
 
         return emu;
     }
-}
 
-impl Chip8 {
-    pub fn load_program(&mut self, path: &str) {
+    pub fn load_program(&mut self, path: &str) -> Result<(), EmuError> {
         let path = Path::new(path);
-        let display = path.display();
-
-        let mut file = match File::open(path) {
-            Ok(file) => file,
-            Err(E) => panic!("Failed to open file {}: {}", display, &E)
-        };
+        let mut file = File::open(path)?;
 
         let mut data = Vec::new();
 
-        file.read_to_end(&mut data).expect("Couldn't read file");
+        file.read_to_end(&mut data)?;
+        return self.load_rom(&data);
+    }
+
+    /// Copy a program into memory at 0x200
+    pub fn load_rom(&mut self, data: &[u8]) -> Result<(), EmuError> {
+        let max = self.memory.len() - 0x200;
+        if data.len() > max {
+            return Err(EmuError::RomTooLarge { size: data.len(), max });
+        }
 
         for (i, byte) in data.iter().enumerate() {
             self.memory[0x200 + i] = *byte;
         }
+        self.rom_hash = savestate::rom_hash(data);
+        return Ok(());
+    }
+
+    /// Make CXKK reproducible, the same seed gives the same numbers
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    /// Hash of the loaded program, as stored in save states
+    pub fn rom_hash(&self) -> u64 {
+        return self.rom_hash;
+    }
+
+    /// Serialize the whole machine, see `load_state`
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = Writer::new();
+        w.bytes(savestate::MAGIC);
+        w.u16(savestate::VERSION);
+        w.u64(self.rom_hash);
+
+        w.quirks(&self.quirks);
+        w.block(&self.memory);
+        w.bytes(&self.registers);
+        w.u16(self.I);
+        w.u16(self.pc);
+        w.u8(self.stack.len() as u8);
+        for addr in self.stack.iter() {
+            w.u16(*addr);
+        }
+        w.u8(self.dt);
+        w.u8(self.st);
+        w.bool(self.hires);
+        w.block(&self.screen);
+        w.bytes(&self.keys);
+        match self.key_wait {
+            KeyWait::Idle => w.u8(0),
+            KeyWait::Waiting => w.u8(1),
+            KeyWait::Held(key) => w.u8(0x10 | key),
+        }
+        w.u8(match self.display_wait {
+            DisplayWait::Idle => 0,
+            DisplayWait::Waiting => 1,
+            DisplayWait::Ready => 2,
+        });
+        w.u32(self.cycles_per_frame);
+        w.bus_policy(self.memory.policy());
+        w.bytes(&self.rpl);
+        w.bool(self.exited);
+        w.u8(self.plane);
+        w.bytes(&self.pattern);
+        w.bool(self.pattern_loaded);
+        w.u8(self.pitch);
+        w.u64(self.rng.state());
+        return w.data;
+    }
+
+    /// Restore a state from `save_state`. It must come from this version of
+    /// the format and the same ROM, on error the machine is left untouched.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), EmuError> {
+        let mut r = savestate::read_header(data, self.rom_hash)?;
+
+        let quirks = r.quirks()?;
+        let mut emu = Chip8::new(quirks);
+        emu.rom_hash = self.rom_hash;
+
+        let memory = r.block()?;
+        if memory.len() != emu.memory.len() {
+            return Err(EmuError::BadState);
+        }
+        emu.memory.copy_from_slice(memory);
+        r.array(&mut emu.registers)?;
+        emu.I = r.u16()?;
+        emu.pc = r.u16()?;
+        let depth = r.u8()? as usize;
+        if depth > STACK_SIZE {
+            return Err(EmuError::BadState);
+        }
+        for _ in 0..depth {
+            emu.stack.push(r.u16()?);
+        }
+        emu.dt = r.u8()?;
+        emu.st = r.u8()?;
+        emu.set_hires(r.bool()?);
+        let screen = r.block()?;
+        if screen.len() != emu.screen.len() {
+            return Err(EmuError::BadState);
+        }
+        emu.screen.copy_from_slice(screen);
+        r.array(&mut emu.keys)?;
+        emu.key_wait = match r.u8()? {
+            0 => KeyWait::Idle,
+            1 => KeyWait::Waiting,
+            key @ 0x10..=0x1F => KeyWait::Held(key & 0xF),
+            _ => return Err(EmuError::BadState),
+        };
+        emu.display_wait = match r.u8()? {
+            0 => DisplayWait::Idle,
+            1 => DisplayWait::Waiting,
+            2 => DisplayWait::Ready,
+            _ => return Err(EmuError::BadState),
+        };
+        emu.cycles_per_frame = r.u32()?;
+        emu.memory.set_policy(r.bus_policy()?);
+        r.array(&mut emu.rpl)?;
+        emu.exited = r.bool()?;
+        emu.plane = r.u8()?;
+        r.array(&mut emu.pattern)?;
+        emu.pattern_loaded = r.bool()?;
+        emu.pitch = r.u8()?;
+        emu.rng = Rng::from_state(r.u64()?).ok_or(EmuError::BadState)?;
+        if !r.is_empty() {
+            return Err(EmuError::BadState);
+        }
+
+        emu.draw = true;
+        *self = emu;
+        return Ok(());
+    }
+
+    pub fn pc(&self) -> u16 {
+        return self.pc;
+    }
+
+    /// The `I` register
+    pub fn index(&self) -> u16 {
+        return self.I;
+    }
+
+    /// V0 to VF
+    pub fn registers(&self) -> &[u8] {
+        return &self.registers;
+    }
+
+    /// Return addresses, the most recent call is last
+    pub fn stack(&self) -> &[u16] {
+        return &self.stack;
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        return &self.memory;
+    }
+
+    /// Out of range accesses let through by `BusPolicy::Log` since the last
+    /// call
+    pub fn take_faults(&mut self) -> Option<Faults> {
+        return self.memory.take_faults();
+    }
+
+    /// Addresses the last instruction stored to, whether or not their values
+    /// changed
+    pub fn written(&self) -> &[usize] {
+        return self.memory.written();
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        return self.dt;
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        return self.st;
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        return self.quirks;
+    }
+
+    /// Which keypad keys are down, 1 for pressed
+    pub fn keys(&self) -> &[u8; 16] {
+        return &self.keys;
+    }
+
+    /// Keypad key `key` was pressed
+    pub fn key_down(&mut self, key: u8) {
+        let key = key & 0xF;
+        self.keys[key as usize] = 1;
+        // A press and release between two frames still finishes an FX0A
+        if self.key_wait == KeyWait::Waiting {
+            self.key_wait = KeyWait::Held(key);
+        }
+    }
+
+    /// Keypad key `key` was released
+    pub fn key_up(&mut self, key: u8) {
+        self.keys[(key & 0xF) as usize] = 0;
+    }
+
+    pub fn key_event(&mut self, event: KeyEvent) {
+        match event {
+            KeyEvent::Down(key) => self.key_down(key),
+            KeyEvent::Up(key) => self.key_up(key),
+        }
+    }
+
+    /// Press and release keys so the keypad matches `keys`, e.g. a frame of a
+    /// movie
+    pub fn set_keys(&mut self, keys: &[u8; 16]) {
+        for (key, down) in keys.iter().enumerate() {
+            if *down != 0 && self.keys[key] == 0 {
+                self.key_down(key as u8);
+            } else if *down == 0 && self.keys[key] != 0 {
+                self.key_up(key as u8);
+            }
+        }
+    }
+
+    /// The instruction at `pc`, if it's inside memory
+    pub fn current_opcode(&self) -> Option<u16> {
+        if !self.memory.reachable(self.pc as usize, 2) {
+            return None;
+        }
+        return Some(self.peek_word(self.pc));
+    }
+
+    pub fn cycles_per_frame(&self) -> u32 {
+        return self.cycles_per_frame;
+    }
+
+    /// Set how many instructions `step_frame` runs, CPU speed is this times 60 Hz
+    pub fn set_cycles_per_frame(&mut self, cycles: u32) {
+        self.cycles_per_frame = cycles;
+    }
+
+    pub fn bus_policy(&self) -> BusPolicy {
+        return self.memory.policy();
+    }
+
+    /// Choose what instructions reaching past the end of memory do, by
+    /// default they fail with `MemoryOutOfBounds`
+    pub fn set_bus_policy(&mut self, policy: BusPolicy) {
+        self.memory.set_policy(policy);
+    }
+
+    /// Width of the active resolution
+    pub fn width(&self) -> usize {
+        return if self.hires { HIRES_WIDTH } else { WIDTH } as usize;
+    }
+
+    /// Height of the active resolution
+    pub fn height(&self) -> usize {
+        return if self.hires { HIRES_HEIGHT } else { HEIGHT } as usize;
+    }
+
+    pub fn hires(&self) -> bool {
+        return self.hires;
+    }
+
+    /// Human readable dump of the CPU state, used when reporting faults
+    pub fn register_dump(&self) -> String {
+        let mut dump = format!("PC {:04X} I {:04X} SP {:X}\n", self.pc, self.I, self.stack.len());
+        for row in self.registers.chunks(4).enumerate() {
+            let (n, regs) = row;
+            let line: Vec<String> = regs.iter().enumerate()
+                .map(|(i, v)| format!("V{:X} {:02X}", n * 4 + i, v))
+                .collect();
+            dump += &line.join(" ");
+            dump += "\n";
+        }
+        dump += &format!("DT {:02X} ST {:02X}", self.dt, self.st);
+        return dump;
+    }
+
+    /// Whether the program has run the SUPER-CHIP exit instruction
+    pub fn exited(&self) -> bool {
+        return self.exited;
+    }
+
+    /// Whether the screen has been cleared and nothing drawn on it since, as
+    /// happens part way through redrawing it
+    pub fn awaiting_redraw(&self) -> bool {
+        return self.cleared;
+    }
+
+    /// Whether the buzzer should be sounding
+    pub fn sound_active(&self) -> bool {
+        return self.st > 0;
+    }
+
+    /// Whether the program has loaded an XO-CHIP audio pattern, in which case
+    /// it should be played instead of a plain tone
+    pub fn uses_audio_pattern(&self) -> bool {
+        return self.pattern_loaded;
+    }
+
+    /// XO-CHIP audio pattern buffer, one bit per sample
+    pub fn audio_pattern(&self) -> &[u8; PATTERN_SIZE] {
+        return &self.pattern;
+    }
+
+    /// Sample rate the audio pattern is played back at
+    pub fn pattern_rate(&self) -> f32 {
+        return 4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0);
+    }
+
+    fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.screen = vec![0; self.width() * self.height()];
+        self.draw = true;
     }
 
     fn inc_pc(&mut self) {
-        self.pc += OPCODE_LEN;
+        self.pc = self.pc.wrapping_add(OPCODE_LEN);
+    }
+
+    fn dec_pc(&mut self) {
+        self.pc = self.pc.wrapping_sub(OPCODE_LEN);
+    }
+
+    /// Apply the bus policy to `len` bytes starting at `addr`
+    fn check_range(&mut self, addr: usize, len: usize) -> Result<(), EmuError> {
+        return self.memory.check(addr, len);
+    }
+
+    fn read_word(&mut self, addr: u16) -> Result<u16, EmuError> {
+        self.check_range(addr as usize, 2)?;
+        return Ok(self.peek_word(addr));
+    }
+
+    /// Read a word without applying the bus policy, past the end reads as 0
+    fn peek_word(&self, addr: u16) -> u16 {
+        return (self.memory.read(addr as usize) as u16) << 8 |
+            self.memory.read(addr as usize + 1) as u16;
+    }
+
+    /// Skip the next instruction, F000 NNNN is twice as long as the others
+    fn skip(&mut self) {
+        // Only a peek, the next instruction may not be there at all
+        if self.peek_word(self.pc.wrapping_add(OPCODE_LEN)) == 0xF000 {
+            self.inc_pc();
+        }
+        self.inc_pc();
     }
 
     fn set_vf(&mut self) {
@@ -117,6 +549,7 @@ impl Chip8 {
         self.registers[0xF] = 0
     }
 
+    #[cfg(test)]
     fn get_vf(&self) -> u8 {
         return self.registers[0xF];
     }
@@ -125,31 +558,50 @@ impl Chip8 {
         self.registers[0xF] = val;
     }
 
-    fn inc_timers(&mut self) {
+    /// Count the timers down, must be called at 60 Hz. This also marks the
+    /// end of a frame for the display wait quirk.
+    pub fn tick_timers(&mut self) {
+        if self.display_wait == DisplayWait::Waiting {
+            self.display_wait = DisplayWait::Ready;
+        }
+
         if self.dt > 0 {
             self.dt -= 1;
         }
 
-        self.do_sound = false;
         if self.st > 0 {
-            if self.st == 1 {
-                self.do_sound = true;
-            }
             self.st -= 1;
         }
     }
 
-    pub fn run_cycle(&mut self) {
-        let opcode = (self.memory[self.pc as usize] as u16) << 8 |
-            self.memory[(self.pc + 1) as usize] as u16;
+    /// Run a single 60 Hz frame: `cycles_per_frame` instructions followed by
+    /// one timer tick. Frames don't depend on wall-clock time, so a sequence of
+    /// them always produces the same result.
+    pub fn step_frame(&mut self) -> Result<(), EmuError> {
+        for _ in 0..self.cycles_per_frame {
+            if self.exited {
+                break;
+            }
+            self.run_cycle()?;
+        }
+        self.tick_timers();
+        return Ok(());
+    }
+
+    /// Fetch and execute one instruction, timers are left alone. Does
+    /// nothing while a DXYN is waiting for the frame to end.
+    pub fn run_cycle(&mut self) -> Result<(), EmuError> {
+        if self.exited || self.display_wait == DisplayWait::Waiting {
+            return Ok(());
+        }
 
-        self.execute_instruction(opcode);
-        self.inc_timers();
+        let opcode = self.read_word(self.pc)?;
 
-        thread::sleep(Duration::from_millis(2));
+        return self.execute_instruction(opcode);
     }
 
-    pub fn execute_instruction(&mut self, opcode: u16) {
+    pub fn execute_instruction(&mut self, opcode: u16) -> Result<(), EmuError> {
+        self.memory.clear_written();
         let instruction = Instruction::new(opcode);
 
         let x = instruction.get_x() as usize;
@@ -163,45 +615,96 @@ impl Chip8 {
                 match instruction.get_kk() {
                     // Clear Screen
                     0xE0 => {
-                        for i in 0..PIXEL_COUNT {
-                            self.screen[i] = 0;
+                        for i in 0..self.screen.len() {
+                            self.screen[i] &= !self.plane;
                         }
                         self.draw = true;
+                        self.cleared = true;
                     },
                     // Return
                     0xEE => {
-                        self.pc = self.stack.pop().unwrap();
+                        self.pc = self.stack.pop().ok_or(EmuError::StackUnderflow)?;
                     }
-                    _ => self.cry(opcode),
+                    // Scroll down n rows
+                    0xC0..=0xCF => {
+                        self.scroll(0, nibble as i32);
+                    },
+                    // Scroll up n rows
+                    0xD0..=0xDF => {
+                        self.scroll(0, -(nibble as i32));
+                    },
+                    // Scroll right 4 pixels
+                    0xFB => {
+                        self.scroll(4, 0);
+                    },
+                    // Scroll left 4 pixels
+                    0xFC => {
+                        self.scroll(-4, 0);
+                    },
+                    // Exit
+                    0xFD => {
+                        self.exited = true;
+                    },
+                    // Low resolution
+                    0xFE => {
+                        self.set_hires(false);
+                    },
+                    // High resolution
+                    0xFF => {
+                        self.set_hires(true);
+                    },
+                    _ => return Err(self.cry(opcode)),
                 }
             },
             // Jump
             1 => {
                 self.pc = addr;
-                self.pc -= OPCODE_LEN;
+                self.dec_pc();
             },
             // Call
             2 => {
+                if self.stack.len() == STACK_SIZE {
+                    return Err(EmuError::StackOverflow);
+                }
                 self.stack.push(self.pc);
                 self.pc = addr;
-                self.pc -= OPCODE_LEN;
+                self.dec_pc();
             },
             // Skip x == kk
             3 => {
                 if self.registers[x] == kk {
-                    self.inc_pc();
+                    self.skip();
                 }
             },
             // Skip x != kk
             4 => {
                 if self.registers[x] != kk {
-                    self.inc_pc();
+                    self.skip();
                 }
             },
-            // Skip if x == y
             5 => {
-                if self.registers[x] == self.registers[y] {
-                    self.inc_pc();
+                match nibble {
+                    // Save x..y to memory at I
+                    2 => {
+                        self.check_range(self.I as usize, x.max(y) - x.min(y) + 1)?;
+                        for (offset, reg) in register_range(x, y).enumerate() {
+                            self.memory.write(self.I as usize + offset, self.registers[reg]);
+                        }
+                    },
+                    // Load x..y from memory at I
+                    3 => {
+                        self.check_range(self.I as usize, x.max(y) - x.min(y) + 1)?;
+                        for (offset, reg) in register_range(x, y).enumerate() {
+                            self.registers[reg] = self.memory.read(self.I as usize + offset);
+                        }
+                    },
+                    // Skip if x == y
+                    0 => {
+                        if self.registers[x] == self.registers[y] {
+                            self.skip();
+                        }
+                    },
+                    _ => return Err(self.cry(opcode)),
                 }
             },
             // Set x = kk
@@ -221,14 +724,23 @@ impl Chip8 {
                     // Set x |= y
                     1 => {
                         self.registers[x] |= self.registers[y];
+                        if self.quirks.vf_reset {
+                            self.unset_vf();
+                        }
                     },
                     // Set x &= y
                     2 => {
                         self.registers[x] &= self.registers[y];
+                        if self.quirks.vf_reset {
+                            self.unset_vf();
+                        }
                     },
                     // Set x ^= y
                     3 => {
                         self.registers[x] ^= self.registers[y];
+                        if self.quirks.vf_reset {
+                            self.unset_vf();
+                        }
                     },
                     // Add y to x
                     4 => {
@@ -250,8 +762,9 @@ impl Chip8 {
                     },
                     // Shift x right
                     6 => {
-                        self.do_set_vf((self.registers[x] & 0x0001) as u8);
-                        self.registers[x] >>= 1;
+                        let val = self.shift_source(x, y);
+                        self.do_set_vf(val & 0x01);
+                        self.registers[x] = val >> 1;
                     },
                     // x = y - x
                     7 => {
@@ -264,17 +777,18 @@ impl Chip8 {
                     },
                     // Shift x left
                     0xE => {
-                        self.do_set_vf(((self.registers[x] & 0x80) as u8) >> 7);
-                        self.registers[x] <<= 1;
+                        let val = self.shift_source(x, y);
+                        self.do_set_vf(val >> 7);
+                        self.registers[x] = val << 1;
                     }
 
-                    _ => self.cry(opcode),
+                    _ => return Err(self.cry(opcode)),
                 }
             },
             // Skip x != y
-            9 => {
+            9 if nibble == 0 => {
                 if self.registers[x] != self.registers[y] {
-                    self.inc_pc();
+                    self.skip();
                 }
             },
             // Set I
@@ -283,75 +797,90 @@ impl Chip8 {
             },
             // Jump add
             0xB => {
-                self.pc = addr + self.registers[0] as u16 - OPCODE_LEN;
+                let offset = if self.quirks.jump_vx {
+                    self.registers[x]
+                } else {
+                    self.registers[0]
+                };
+                self.pc = addr + offset as u16;
+                self.dec_pc();
             },
             // Set x = kk & rand
             0xC => {
-                self.registers[x] = kk & rand::random::<u8>();
+                self.registers[x] = kk & self.rng.next_u8();
+            },
+            // Draw sprite, n == 0 draws a 16x16 sprite
+            0xD if self.quirks.display_wait && self.display_wait == DisplayWait::Idle => {
+                // Run this again once the frame ends
+                self.display_wait = DisplayWait::Waiting;
+                self.dec_pc();
             },
-            // Draw sprite
             0xD => {
-                let x = self.registers[x] as i32;
-                let y = self.registers[y]  as i32;
-                let n = nibble as u16;
-
-                self.unset_vf();
-                for i in self.I..(self.I + n) {
-                    let row = (i - self.I) as u8;
-                    let bits = BitVec::from_bytes(&[self.memory[i as usize]]);
-
-                    for j in 0..8 {
-                        let xs = x + j;
-                        let ys = y + row as i32;
-
-                        if in_bounds(xs, ys) {
-                            let address = (64 * ys) + xs;
-                            if bits[j as usize] {
-                                if self.screen[address as usize] == 1 {
-                                    self.set_vf();
-                                }
-                                self.screen[address as usize] ^= 1;
-                            }
-                        }
-                    }
-                }
-                self.draw = true;
+                self.draw_sprite(x, y, nibble)?;
+                self.cleared = false;
+                self.display_wait = DisplayWait::Idle;
             },
             0xE => {
               match kk {
                   // Skips if key stored in x is pressed
                   0x9E => {
                     if self.keys[self.registers[x] as usize] == 1 {
-                        self.inc_pc();
+                        self.skip();
                     }
                   },
                   // Skip if not pressed
                   0xA1 => {
                       if self.keys[self.registers[x] as usize] == 0{
-                          self.inc_pc();
+                          self.skip();
                       }
                   },
-                  _ => self.cry(opcode),
+                  _ => return Err(self.cry(opcode)),
               }
             },
             0xF => {
                 match kk {
+                    // Set I to the following 16 bit word
+                    0x00 if x == 0 => {
+                        self.I = self.read_word(self.pc.wrapping_add(OPCODE_LEN))?;
+                        self.inc_pc();
+                    },
+                    // Select drawing planes
+                    0x01 => {
+                        self.plane = x as u8 & 0x3;
+                    },
+                    // Load audio pattern from I
+                    0x02 if x == 0 => {
+                        let start = self.I as usize;
+                        self.check_range(start, PATTERN_SIZE)?;
+                        for i in 0..PATTERN_SIZE {
+                            self.pattern[i] = self.memory.read(start + i);
+                        }
+                        self.pattern_loaded = true;
+                    },
                     // Set x to dt
                     0x07 => {
                         self.registers[x] = self.dt;
                     },
                     // Wait for keypress
                     0x0A => {
-                        let mut found = false;
-                        for k in 0..15 {
-                            if self.keys[k as usize] == 1 {
-                                self.registers[x] = k as u8;
-                                found = true;
-                                break;
-                            }
-                        }
-                        if !found {
-                            self.pc -= OPCODE_LEN;
+                        let held = self.keys.iter().position(|k| *k != 0).map(|k| k as u8);
+                        match self.key_wait {
+                            _ if !self.quirks.key_release => match held {
+                                Some(k) => self.registers[x] = k,
+                                None => self.dec_pc(),
+                            },
+                            KeyWait::Held(k) if self.keys[k as usize] == 0 => {
+                                self.registers[x] = k;
+                                self.key_wait = KeyWait::Idle;
+                            },
+                            KeyWait::Held(_) => self.dec_pc(),
+                            KeyWait::Idle | KeyWait::Waiting => {
+                                self.key_wait = match held {
+                                    Some(k) => KeyWait::Held(k),
+                                    None => KeyWait::Waiting,
+                                };
+                                self.dec_pc();
+                            },
                         }
                     },
                     // Set dt to x
@@ -364,51 +893,191 @@ impl Chip8 {
                     },
                     // Add x to I
                     0x1E => {
-                        self.I += self.registers[x] as u16;
+                        let sum = self.I as usize + self.registers[x] as usize;
+                        if self.quirks.index_overflow {
+                            self.registers[0xF] = (sum >= self.memory.len()) as u8;
+                        }
+                        self.I = sum as u16;
                     },
                     // Set I top loc of char
                     0x29 => {
                         self.I = 5 * self.registers[x] as u16;
                     },
+                    // Set pitch to x
+                    0x3A => {
+                        self.pitch = self.registers[x];
+                    },
+                    // Set I to loc of big char
+                    0x30 => {
+                        self.I = (BIG_FONT_ADDR + 10 * self.registers[x] as usize) as u16;
+                    },
                     // store bcd of x
                     0x33 => {
                         let val = self.registers[x];
                         let index = self.I as usize;
-                        self.memory[index] = val / 100;
-                        self.memory[index + 1] = (val / 10) % 10;
-                        self.memory[index + 2] = (val % 100) % 10;
+                        self.check_range(index, 3)?;
+                        self.memory.write(index, val / 100);
+                        self.memory.write(index + 1, (val / 10) % 10);
+                        self.memory.write(index + 2, (val % 100) % 10);
                     }
                     // Store registers
                     0x55 => {
-                        for i in 0..(x + 1) as usize {
-                            self.memory[self.I as usize + i] = self.registers[i];
+                        self.check_range(self.I as usize, x + 1)?;
+                        for i in 0..=x {
+                            self.memory.write(self.I as usize + i, self.registers[i]);
                         }
+                        self.inc_index(x);
                     },
                     // load registers
                     0x65 => {
-                        for i in 0..(x + 1) as usize {
-                            self.registers[i] = self.memory[self.I as usize + i];
+                        self.check_range(self.I as usize, x + 1)?;
+                        for i in 0..=x {
+                            self.registers[i] = self.memory.read(self.I as usize + i);
+                        }
+                        self.inc_index(x);
+                    },
+                    // Store registers in RPL flags
+                    0x75 => {
+                        for i in 0..=x {
+                            self.rpl[i] = self.registers[i];
                         }
                     },
-                    _ => self.cry(opcode),
+                    // Load registers from RPL flags
+                    0x85 => {
+                        for i in 0..=x {
+                            self.registers[i] = self.rpl[i];
+                        }
+                    },
+                    _ => return Err(self.cry(opcode)),
                 }
             }
-            _ => self.cry(opcode),
+            _ => return Err(self.cry(opcode)),
         }
         self.inc_pc();
+        return Ok(());
+    }
+
+    fn draw_sprite(&mut self, x: usize, y: usize, n: u8) -> Result<(), EmuError> {
+        let width = self.width() as i32;
+        let height = self.height() as i32;
+        let mut x = self.registers[x] as i32;
+        let mut y = self.registers[y] as i32;
+        if self.quirks.sprite_edge == SpriteEdge::WrapStart {
+            x %= width;
+            y %= height;
+        }
+        let (cols, rows) = if n == 0 { (16, 16) } else { (8, n as usize) };
+        let row_bytes = cols as usize / 8;
+        let planes = self.plane;
+        let sprite_len = rows * row_bytes * planes.count_ones() as usize;
+        self.check_range(self.I as usize, sprite_len)?;
+
+        self.unset_vf();
+        // Each selected plane takes the next sprite's worth of data from I.
+        // Counted in usize, a sprite ending at the top of 64 KiB would
+        // overflow a u16
+        let mut start = self.I as usize;
+        // Rows that hit a lit pixel or fell off the bottom in either plane,
+        // for SUPER-CHIP
+        let mut hit_rows = [false; 16];
+        for plane in [1u8, 2].iter().copied().filter(|p| planes & p != 0) {
+            for (row, hit) in hit_rows[..rows].iter_mut().enumerate() {
+                *hit |= self.quirks.sprite_edge != SpriteEdge::Wrap && y + row as i32 >= height;
+                let i = start + row * row_bytes;
+                let mut bytes = [0; 2];
+                for (b, byte) in bytes[..row_bytes].iter_mut().enumerate() {
+                    *byte = self.memory.read(i + b);
+                }
+                let bits = BitVec::from_bytes(&bytes[..row_bytes]);
+
+                for j in 0..cols {
+                    let mut xs = x + j;
+                    let mut ys = y + row as i32;
+                    if self.quirks.sprite_edge == SpriteEdge::Wrap {
+                        xs %= width;
+                        ys %= height;
+                    }
+
+                    if in_bounds(xs, ys, width, height) {
+                        let address = (width * ys) + xs;
+                        if bits[j as usize] {
+                            if self.screen[address as usize] & plane != 0 {
+                                self.set_vf();
+                                *hit = true;
+                            }
+                            self.screen[address as usize] ^= plane;
+                        }
+                    }
+                }
+            }
+            start += rows * row_bytes;
+        }
+        if self.hires && self.quirks.collision_rows {
+            self.registers[0xF] = hit_rows.iter().filter(|hit| **hit).count() as u8;
+        }
+        self.draw = true;
+        return Ok(());
+    }
+
+    /// Shift the selected planes, pixels moved off screen are lost
+    fn scroll(&mut self, dx: i32, dy: i32) {
+        let width = self.width() as i32;
+        let height = self.height() as i32;
+        let old = self.screen.clone();
+
+        for ys in 0..height {
+            for xs in 0..width {
+                let (src_x, src_y) = (xs - dx, ys - dy);
+                let moved = if in_bounds(src_x, src_y, width, height) {
+                    old[(src_y * width + src_x) as usize] & self.plane
+                } else {
+                    0
+                };
+                let address = (ys * width + xs) as usize;
+                self.screen[address] = (self.screen[address] & !self.plane) | moved;
+            }
+        }
+        self.draw = true;
+    }
+
+    fn shift_source(&self, x: usize, y: usize) -> u8 {
+        return if self.quirks.shift_vy {
+            self.registers[y]
+        } else {
+            self.registers[x]
+        };
+    }
+
+    fn inc_index(&mut self, x: usize) {
+        match self.quirks.load_store {
+            IndexIncrement::None => {},
+            IndexIncrement::X => self.I = self.I.wrapping_add(x as u16),
+            IndexIncrement::XPlusOne => self.I = self.I.wrapping_add(x as u16 + 1),
+        }
     }
 
-    fn cry(&self, opcode: u16) {
-        panic!("Opcode {:#X} is bad", opcode);
+    fn cry(&self, opcode: u16) -> EmuError {
+        return EmuError::InvalidOpcode { pc: self.pc, opcode };
     }
 }
 
 
-fn in_bounds(xs: i32, ys: i32) -> bool {
-    return (0..WIDTH as i32).contains(&xs) && (0..HEIGHT as i32).contains(&ys);
+/// Registers from x to y inclusive, counting down if y < x
+fn register_range(x: usize, y: usize) -> Box<dyn Iterator<Item = usize>> {
+    return if x <= y {
+        Box::new(x..=y)
+    } else {
+        Box::new((y..=x).rev())
+    };
+}
+
+fn in_bounds(xs: i32, ys: i32, width: i32, height: i32) -> bool {
+    return (0..width).contains(&xs) && (0..height).contains(&ys);
 }
 
 #[cfg(test)]
+// Tests poke fields of a default machine directly
+#[allow(clippy::field_reassign_with_default)]
 mod tests {
     use super::*;
 
@@ -420,7 +1089,7 @@ mod tests {
             inter.memory[i] = i as u8;
         }
 
-        inter.execute_instruction(0xF965);
+        inter.execute_instruction(0xF965).unwrap();
 
         for i in 0..5usize {
             assert_eq!(inter.registers[i], i as u8);
@@ -435,7 +1104,7 @@ mod tests {
             inter.registers[i] = i as u8;
         }
 
-        inter.execute_instruction(0xF955);
+        inter.execute_instruction(0xF955).unwrap();
 
         for i in 0..5usize {
             assert_eq!(inter.memory[i], i as u8);
@@ -446,7 +1115,7 @@ mod tests {
     fn test_bcd() {
         let mut inter = Chip8::default();
         inter.registers[1] = 123;
-        inter.execute_instruction(0xF133);
+        inter.execute_instruction(0xF133).unwrap();
         for i in 0..3 {
             assert_eq!(inter.memory[i] as usize, i+1);
         }
@@ -457,7 +1126,7 @@ mod tests {
         let mut inter = Chip8::default();
         inter.registers[1] = 20;
         inter.I = 3;
-        inter.execute_instruction(0xF129);
+        inter.execute_instruction(0xF129).unwrap();
         assert_eq!(inter.I, 20 * 5);
     }
 
@@ -466,15 +1135,39 @@ mod tests {
         let mut inter = Chip8::default();
         inter.registers[1] = 20;
         inter.I = 3;
-        inter.execute_instruction(0xF11E);
+        inter.execute_instruction(0xF11E).unwrap();
         assert_eq!(inter.I, 23);
     }
 
+    #[test]
+    fn test_add_x_to_I_overflows_u16() {
+        let mut inter = Chip8::default();
+        inter.registers[1] = 0x20;
+        inter.I = 0xFFF0;
+        inter.execute_instruction(0xF11E).unwrap();
+        assert_eq!(inter.I, 0x10);
+        assert_eq!(inter.get_vf(), 0);
+    }
+
+    #[test]
+    fn test_quirk_index_overflow() {
+        let mut inter = with_quirks(|q| q.index_overflow = true);
+        inter.registers[1] = 0x20;
+        inter.I = 0xFF0;
+        inter.execute_instruction(0xF11E).unwrap();
+        assert_eq!(inter.I, 0x1010);
+        assert_eq!(inter.get_vf(), 1);
+
+        inter.I = 0x300;
+        inter.execute_instruction(0xF11E).unwrap();
+        assert_eq!(inter.get_vf(), 0);
+    }
+
     #[test]
     fn test_set_st_to_x() {
         let mut inter = Chip8::default();
         inter.registers[1] = 10;
-        inter.execute_instruction(0xF118);
+        inter.execute_instruction(0xF118).unwrap();
         assert_eq!(inter.st, 10);
     }
 
@@ -482,7 +1175,7 @@ mod tests {
     fn test_set_dt_to_x() {
         let mut inter = Chip8::default();
         inter.registers[1] = 10;
-        inter.execute_instruction(0xF115);
+        inter.execute_instruction(0xF115).unwrap();
         assert_eq!(inter.dt, 10);
     }
 
@@ -490,7 +1183,7 @@ mod tests {
     fn test_wait_keypress_not_pressed() {
         let mut inter = Chip8::default();
         let last_pc = inter.pc;
-        inter.execute_instruction(0xF10A);
+        inter.execute_instruction(0xF10A).unwrap();
         assert_eq!(last_pc, inter.pc);
     }
 
@@ -499,16 +1192,90 @@ mod tests {
         let mut inter = Chip8::default();
         inter.keys[1] = 1;
         let last_pc = inter.pc;
-        inter.execute_instruction(0xF10A);
+        inter.execute_instruction(0xF10A).unwrap();
         assert_eq!(last_pc + 2, inter.pc);
         assert_eq!(inter.registers[1], 1);
     }
 
+    #[test]
+    fn test_wait_keypress_key_f() {
+        let mut inter = Chip8::default();
+        inter.key_down(0xF);
+        let last_pc = inter.pc;
+        inter.execute_instruction(0xF10A).unwrap();
+        assert_eq!(last_pc + 2, inter.pc);
+        assert_eq!(inter.registers[1], 0xF);
+    }
+
+    #[test]
+    fn test_wait_keypress_release() {
+        let mut inter = Chip8::new(Quirks::VIP);
+        let last_pc = inter.pc;
+        inter.execute_instruction(0xF10A).unwrap();
+        assert_eq!(last_pc, inter.pc);
+
+        // Pressing isn't enough, the key has to come back up
+        inter.key_down(0xF);
+        inter.execute_instruction(0xF10A).unwrap();
+        inter.execute_instruction(0xF10A).unwrap();
+        assert_eq!(last_pc, inter.pc);
+
+        inter.key_up(0xF);
+        inter.execute_instruction(0xF10A).unwrap();
+        assert_eq!(last_pc + 2, inter.pc);
+        assert_eq!(inter.registers[1], 0xF);
+    }
+
+    #[test]
+    fn test_wait_keypress_release_held_first() {
+        let mut inter = Chip8::new(Quirks::VIP);
+        inter.key_down(0xA);
+        let last_pc = inter.pc;
+        inter.execute_instruction(0xF20A).unwrap();
+        inter.key_down(0xB);
+        inter.key_up(0xB);
+        inter.execute_instruction(0xF20A).unwrap();
+        assert_eq!(last_pc, inter.pc);
+
+        inter.key_up(0xA);
+        inter.execute_instruction(0xF20A).unwrap();
+        assert_eq!(inter.registers[2], 0xA);
+
+        // The next FX0A waits for a new press
+        inter.execute_instruction(0xF20A).unwrap();
+        assert_eq!(last_pc + 2, inter.pc);
+    }
+
+    #[test]
+    fn test_wait_keypress_tap_between_cycles() {
+        let mut inter = Chip8::new(Quirks::VIP);
+        let last_pc = inter.pc;
+        inter.execute_instruction(0xF10A).unwrap();
+        inter.key_down(0xF);
+        inter.key_up(0xF);
+        inter.execute_instruction(0xF10A).unwrap();
+        assert_eq!(last_pc + 2, inter.pc);
+        assert_eq!(inter.registers[1], 0xF);
+    }
+
+    #[test]
+    fn test_set_keys() {
+        let mut inter = Chip8::default();
+        let mut keys = [0; 16];
+        keys[0xF] = 1;
+        keys[2] = 1;
+        inter.set_keys(&keys);
+        assert_eq!(inter.keys(), &keys);
+        keys[2] = 0;
+        inter.set_keys(&keys);
+        assert_eq!(inter.keys(), &keys);
+    }
+
     #[test]
     fn test_set_x_to_dt() {
         let mut inter = Chip8::default();
         inter.dt = 20;
-        inter.execute_instruction(0xF107);
+        inter.execute_instruction(0xF107).unwrap();
         assert_eq!(inter.registers[1], 20);
     }
 
@@ -518,7 +1285,7 @@ mod tests {
         let last_pc = inter.pc;
         inter.registers[1] = 4;
         inter.keys[4] = 0;
-        inter.execute_instruction(0xE1A1);
+        inter.execute_instruction(0xE1A1).unwrap();
         assert_eq!(inter.pc, last_pc + 4);
     }
 
@@ -528,7 +1295,7 @@ mod tests {
         let last_pc = inter.pc;
         inter.registers[1] = 4;
         inter.keys[4] = 1;
-        inter.execute_instruction(0xE1A1);
+        inter.execute_instruction(0xE1A1).unwrap();
         assert_eq!(inter.pc, last_pc + 2);
     }
 
@@ -538,7 +1305,7 @@ mod tests {
         let last_pc = inter.pc;
         inter.registers[1] = 4;
         inter.keys[4] = 0;
-        inter.execute_instruction(0xE19E);
+        inter.execute_instruction(0xE19E).unwrap();
         assert_eq!(inter.pc, last_pc + 2);
     }
 
@@ -548,47 +1315,47 @@ mod tests {
         let last_pc = inter.pc;
         inter.registers[1] = 4;
         inter.keys[4] = 1;
-        inter.execute_instruction(0xE19E);
+        inter.execute_instruction(0xE19E).unwrap();
         assert_eq!(inter.pc, last_pc + 4);
     }
 
     #[test]
     fn test_in_bounds() {
-        assert!(in_bounds(23, 12));
+        assert!(in_bounds(23, 12, WIDTH as i32, HEIGHT as i32));
     }
 
     #[test]
     fn test_in_bounds_false1() {
-        assert!(!in_bounds(23, 87));
+        assert!(!in_bounds(23, 87, WIDTH as i32, HEIGHT as i32));
     }
 
     #[test]
     fn test_in_bounds_false2() {
-        assert!(!in_bounds(98, 87));
+        assert!(!in_bounds(98, 87, WIDTH as i32, HEIGHT as i32));
     }
 
     #[test]
     fn test_in_bounds_false3() {
-        assert!(!in_bounds(98, 12));
+        assert!(!in_bounds(98, 12, WIDTH as i32, HEIGHT as i32));
     }
 
     #[test]
     fn test_in_bounds_false4() {
-        assert!(!in_bounds(64, 12));
+        assert!(!in_bounds(64, 12, WIDTH as i32, HEIGHT as i32));
     }
 
     #[test]
     fn test_jump_add() {
         let mut inter = Chip8::default();
         inter.registers[0] = 10;
-        inter.execute_instruction(0xB345);
+        inter.execute_instruction(0xB345).unwrap();
         assert_eq!(inter.pc, 0x345 + 10);
     }
 
     #[test]
     fn test_set_I() {
         let mut inter = Chip8::default();
-        inter.execute_instruction(0xA645);
+        inter.execute_instruction(0xA645).unwrap();
         assert_eq!(inter.I, 0x645);
     }
 
@@ -598,7 +1365,7 @@ mod tests {
         let curr_pc = inter.pc;
         inter.registers[5] = 5;
         inter.registers[6] = 5;
-        inter.execute_instruction(0x9560);
+        inter.execute_instruction(0x9560).unwrap();
         assert_eq!(inter.pc, curr_pc + 2);
     }
 
@@ -608,7 +1375,7 @@ mod tests {
         let curr_pc = inter.pc;
         inter.registers[5] = 5;
         inter.registers[6] = 10;
-        inter.execute_instruction(0x9560);
+        inter.execute_instruction(0x9560).unwrap();
         assert_eq!(inter.pc, curr_pc + 4);
     }
 
@@ -616,7 +1383,7 @@ mod tests {
     fn test_left_x_right_nobit() {
         let mut inter = Chip8::default();
         inter.registers[2] = 0x14;
-        inter.execute_instruction(0x820E);
+        inter.execute_instruction(0x820E).unwrap();
         assert_eq!(inter.registers[2], 0x14 << 1);
         assert_eq!(inter.get_vf(), 0);
     }
@@ -625,7 +1392,7 @@ mod tests {
     fn test_left_x_right_bit() {
         let mut inter = Chip8::default();
         inter.registers[2] = 0x86;
-        inter.execute_instruction(0x820E);
+        inter.execute_instruction(0x820E).unwrap();
         assert_eq!(inter.registers[2], 0x86 << 1);
         assert_eq!(inter.get_vf(), 1);
     }
@@ -635,7 +1402,7 @@ mod tests {
         let mut inter = Chip8::default();
         inter.registers[5] = 4;
         inter.registers[3] = 0;
-        inter.execute_instruction(0x8537);
+        inter.execute_instruction(0x8537).unwrap();
         assert_eq!(inter.registers[5], 252);
         assert_eq!(inter.get_vf(), 0);
     }
@@ -645,7 +1412,7 @@ mod tests {
         let mut inter = Chip8::default();
         inter.registers[5] = 4;
         inter.registers[3] = 10;
-        inter.execute_instruction(0x8537);
+        inter.execute_instruction(0x8537).unwrap();
         assert_eq!(inter.registers[5], 6);
         assert_eq!(inter.get_vf(), 1);
     }
@@ -654,7 +1421,7 @@ mod tests {
     fn test_shift_x_right_even() {
         let mut inter = Chip8::default();
         inter.registers[2] = 20;
-        inter.execute_instruction(0x8206);
+        inter.execute_instruction(0x8206).unwrap();
         assert_eq!(inter.registers[2], 10);
         assert_eq!(inter.get_vf(), 0);
     }
@@ -663,7 +1430,7 @@ mod tests {
     fn test_shift_x_right_odd() {
         let mut inter = Chip8::default();
         inter.registers[2] = 21;
-        inter.execute_instruction(0x8206);
+        inter.execute_instruction(0x8206).unwrap();
         assert_eq!(inter.registers[2], 21/2);
         assert_eq!(inter.get_vf(), 1);
     }
@@ -673,7 +1440,7 @@ mod tests {
         let mut inter = Chip8::default();
         inter.registers[5] = 0;
         inter.registers[3] = 4;
-        inter.execute_instruction(0x8535);
+        inter.execute_instruction(0x8535).unwrap();
         assert_eq!(inter.registers[5], 252);
         assert_eq!(inter.get_vf(), 0);
     }
@@ -683,7 +1450,7 @@ mod tests {
         let mut inter = Chip8::default();
         inter.registers[5] = 10;
         inter.registers[3] = 4;
-        inter.execute_instruction(0x8535);
+        inter.execute_instruction(0x8535).unwrap();
         assert_eq!(inter.registers[5], 6);
         assert_eq!(inter.get_vf(), 1);
     }
@@ -693,7 +1460,7 @@ mod tests {
         let mut inter = Chip8::default();
         inter.registers[5] = 255;
         inter.registers[3] = 4;
-        inter.execute_instruction(0x8534);
+        inter.execute_instruction(0x8534).unwrap();
         assert_eq!(inter.registers[5], 3);
         assert_eq!(inter.get_vf(), 1);
     }
@@ -703,7 +1470,7 @@ mod tests {
         let mut inter = Chip8::default();
         inter.registers[5] = 10;
         inter.registers[3] = 4;
-        inter.execute_instruction(0x8534);
+        inter.execute_instruction(0x8534).unwrap();
         assert_eq!(inter.registers[5], 14);
         assert_eq!(inter.get_vf(), 0);
     }
@@ -713,7 +1480,7 @@ mod tests {
         let mut inter = Chip8::default();
         inter.registers[5] = 10;
         inter.registers[3] = 4;
-        inter.execute_instruction(0x8533);
+        inter.execute_instruction(0x8533).unwrap();
         assert_eq!(inter.registers[5], 10 ^ 4);
     }
 
@@ -722,7 +1489,7 @@ mod tests {
         let mut inter = Chip8::default();
         inter.registers[5] = 10;
         inter.registers[3] = 4;
-        inter.execute_instruction(0x8532);
+        inter.execute_instruction(0x8532).unwrap();
         assert_eq!(inter.registers[5], 10 & 4);
     }
 
@@ -731,7 +1498,7 @@ mod tests {
         let mut inter = Chip8::default();
         inter.registers[5] = 10;
         inter.registers[3] = 4;
-        inter.execute_instruction(0x8531);
+        inter.execute_instruction(0x8531).unwrap();
         assert_eq!(inter.registers[5], 10 | 4);
     }
 
@@ -739,14 +1506,14 @@ mod tests {
     fn test_set_x_y() {
         let mut inter = Chip8::default();
         inter.registers[3] = 11;
-        inter.execute_instruction(0x8430);
+        inter.execute_instruction(0x8430).unwrap();
         assert_eq!(inter.registers[4], 11);
     }
 
     #[test]
     fn test_add_kk_x_nowrap() {
         let mut inter = Chip8::default();
-        inter.execute_instruction(0x7733);
+        inter.execute_instruction(0x7733).unwrap();
         assert_eq!(inter.registers[7], 0x33);
     }
 
@@ -754,16 +1521,16 @@ mod tests {
     fn test_add_kk_x_wrap() {
         let mut inter = Chip8::default();
         inter.registers[7] = 255;
-        inter.execute_instruction(0x770A);
+        inter.execute_instruction(0x770A).unwrap();
         assert_eq!(inter.registers[7], 9);
     }
 
     #[test]
     fn test_clear_screen() {
         let mut inter = Chip8::default();
-        inter.screen = [2; PIXEL_COUNT];
+        inter.screen = vec![1; PIXEL_COUNT];
 
-        inter.execute_instruction(0x00E0);
+        inter.execute_instruction(0x00E0).unwrap();
         assert_eq!(inter.screen.iter().sum::<u8>(), 0);
     }
 
@@ -772,14 +1539,14 @@ mod tests {
         let mut inter = Chip8::default();
         inter.pc = 700;
         inter.stack.push(512);
-        inter.execute_instruction(0x00EE);
+        inter.execute_instruction(0x00EE).unwrap();
         assert_eq!(inter.pc, 514);
     }
 
     #[test]
     fn test_jump() {
         let mut inter = Chip8::default();
-        inter.execute_instruction(0x1666);
+        inter.execute_instruction(0x1666).unwrap();
         assert_eq!(inter.pc, 0x666);
     }
 
@@ -787,7 +1554,7 @@ mod tests {
     fn test_call() {
         let mut inter = Chip8::default();
         inter.pc = 0x444;
-        inter.execute_instruction(0x2555);
+        inter.execute_instruction(0x2555).unwrap();
         assert_eq!(inter.pc, 0x555);
         assert_eq!(*inter.stack.last().unwrap(), 0x444);
     }
@@ -797,7 +1564,7 @@ mod tests {
         let mut inter = Chip8::default();
         let curr_pc = inter.pc;
         inter.registers[5] = 5;
-        inter.execute_instruction(0x3505);
+        inter.execute_instruction(0x3505).unwrap();
         assert_eq!(inter.pc, curr_pc + 4);
     }
 
@@ -806,7 +1573,7 @@ mod tests {
         let mut inter = Chip8::default();
         let curr_pc = inter.pc;
         inter.registers[5] = 8;
-        inter.execute_instruction(0x3505);
+        inter.execute_instruction(0x3505).unwrap();
         assert_eq!(inter.pc, curr_pc + 2);
     }
 
@@ -815,7 +1582,7 @@ mod tests {
         let mut inter = Chip8::default();
         let curr_pc = inter.pc;
         inter.registers[5] = 5;
-        inter.execute_instruction(0x4508);
+        inter.execute_instruction(0x4508).unwrap();
         assert_eq!(inter.pc, curr_pc + 4);
     }
 
@@ -824,7 +1591,7 @@ mod tests {
         let mut inter = Chip8::default();
         let curr_pc = inter.pc;
         inter.registers[5] = 5;
-        inter.execute_instruction(0x4505);
+        inter.execute_instruction(0x4505).unwrap();
         assert_eq!(inter.pc, curr_pc + 2);
     }
 
@@ -834,7 +1601,7 @@ mod tests {
         let curr_pc = inter.pc;
         inter.registers[2] = 5;
         inter.registers[3] = 5;
-        inter.execute_instruction(0x5231);
+        inter.execute_instruction(0x5230).unwrap();
         assert_eq!(inter.pc, curr_pc + 4);
     }
 
@@ -844,14 +1611,951 @@ mod tests {
         let curr_pc = inter.pc;
         inter.registers[2] = 5;
         inter.registers[3] = 10;
-        inter.execute_instruction(0x5231);
+        inter.execute_instruction(0x5230).unwrap();
         assert_eq!(inter.pc, curr_pc + 2);
     }
 
     #[test]
     fn test_set_x_kk() {
         let mut inter = Chip8::default();
-        inter.execute_instruction(0x6744);
+        inter.execute_instruction(0x6744).unwrap();
         assert_eq!(inter.registers[7], 0x44);
     }
+
+    fn with_quirks(f: impl Fn(&mut Quirks)) -> Chip8 {
+        let mut quirks = Quirks::default();
+        f(&mut quirks);
+        return Chip8::new(quirks);
+    }
+
+    #[test]
+    fn test_quirk_shift_right_vx() {
+        let mut inter = with_quirks(|q| q.shift_vy = false);
+        inter.registers[2] = 20;
+        inter.registers[3] = 7;
+        inter.execute_instruction(0x8236).unwrap();
+        assert_eq!(inter.registers[2], 10);
+        assert_eq!(inter.get_vf(), 0);
+    }
+
+    #[test]
+    fn test_quirk_shift_right_vy() {
+        let mut inter = with_quirks(|q| q.shift_vy = true);
+        inter.registers[2] = 20;
+        inter.registers[3] = 7;
+        inter.execute_instruction(0x8236).unwrap();
+        assert_eq!(inter.registers[2], 3);
+        assert_eq!(inter.registers[3], 7);
+        assert_eq!(inter.get_vf(), 1);
+    }
+
+    #[test]
+    fn test_quirk_shift_left_vx() {
+        let mut inter = with_quirks(|q| q.shift_vy = false);
+        inter.registers[2] = 0x14;
+        inter.registers[3] = 0x81;
+        inter.execute_instruction(0x823E).unwrap();
+        assert_eq!(inter.registers[2], 0x28);
+        assert_eq!(inter.get_vf(), 0);
+    }
+
+    #[test]
+    fn test_quirk_shift_left_vy() {
+        let mut inter = with_quirks(|q| q.shift_vy = true);
+        inter.registers[2] = 0x14;
+        inter.registers[3] = 0x81;
+        inter.execute_instruction(0x823E).unwrap();
+        assert_eq!(inter.registers[2], 0x02);
+        assert_eq!(inter.get_vf(), 1);
+    }
+
+    #[test]
+    fn test_quirk_load_store_none() {
+        let mut inter = with_quirks(|q| q.load_store = IndexIncrement::None);
+        inter.I = 0x300;
+        inter.execute_instruction(0xF355).unwrap();
+        assert_eq!(inter.I, 0x300);
+        inter.execute_instruction(0xF365).unwrap();
+        assert_eq!(inter.I, 0x300);
+    }
+
+    #[test]
+    fn test_quirk_load_store_x() {
+        let mut inter = with_quirks(|q| q.load_store = IndexIncrement::X);
+        inter.I = 0x300;
+        inter.execute_instruction(0xF355).unwrap();
+        assert_eq!(inter.I, 0x303);
+        inter.execute_instruction(0xF365).unwrap();
+        assert_eq!(inter.I, 0x306);
+    }
+
+    #[test]
+    fn test_quirk_load_store_x_plus_one() {
+        let mut inter = with_quirks(|q| q.load_store = IndexIncrement::XPlusOne);
+        inter.I = 0x300;
+        inter.execute_instruction(0xF355).unwrap();
+        assert_eq!(inter.I, 0x304);
+        inter.execute_instruction(0xF365).unwrap();
+        assert_eq!(inter.I, 0x308);
+    }
+
+    #[test]
+    fn test_quirk_jump_v0() {
+        let mut inter = with_quirks(|q| q.jump_vx = false);
+        inter.registers[0] = 10;
+        inter.registers[3] = 20;
+        inter.execute_instruction(0xB345).unwrap();
+        assert_eq!(inter.pc, 0x345 + 10);
+    }
+
+    #[test]
+    fn test_quirk_jump_vx() {
+        let mut inter = with_quirks(|q| q.jump_vx = true);
+        inter.registers[0] = 10;
+        inter.registers[3] = 20;
+        inter.execute_instruction(0xB345).unwrap();
+        assert_eq!(inter.pc, 0x345 + 20);
+    }
+
+    #[test]
+    fn test_quirk_vf_reset_off() {
+        for opcode in [0x8231u16, 0x8232, 0x8233].iter() {
+            let mut inter = with_quirks(|q| q.vf_reset = false);
+            inter.set_vf();
+            inter.execute_instruction(*opcode).unwrap();
+            assert_eq!(inter.get_vf(), 1);
+        }
+    }
+
+    #[test]
+    fn test_quirk_vf_reset_on() {
+        for opcode in [0x8231u16, 0x8232, 0x8233].iter() {
+            let mut inter = with_quirks(|q| q.vf_reset = true);
+            inter.set_vf();
+            inter.execute_instruction(*opcode).unwrap();
+            assert_eq!(inter.get_vf(), 0);
+        }
+    }
+
+    #[test]
+    fn test_quirk_sprite_clip() {
+        let mut inter = with_quirks(|q| q.sprite_edge = SpriteEdge::Clip);
+        inter.memory[0x300] = 0xFF;
+        inter.I = 0x300;
+        inter.registers[0] = 60;
+        inter.registers[1] = 0;
+        inter.execute_instruction(0xD011).unwrap();
+        assert_eq!(inter.screen[63], 1);
+        assert_eq!(inter.screen[64], 0);
+        assert_eq!(inter.screen[0], 0);
+    }
+
+    #[test]
+    fn test_quirk_sprite_wrap() {
+        let mut inter = with_quirks(|q| q.sprite_edge = SpriteEdge::Wrap);
+        inter.memory[0x300] = 0xFF;
+        inter.I = 0x300;
+        inter.registers[0] = 60;
+        inter.registers[1] = 0;
+        inter.execute_instruction(0xD011).unwrap();
+        assert_eq!(inter.screen[63], 1);
+        assert_eq!(inter.screen[64], 0);
+        assert_eq!(inter.screen[0], 1);
+        assert_eq!(inter.screen[3], 1);
+        assert_eq!(inter.screen[4], 0);
+    }
+
+    #[test]
+    fn test_quirk_sprite_start_off_screen() {
+        // X = 70 is off the 64 pixel wide screen
+        for (edge, drawn) in [(SpriteEdge::Clip, false), (SpriteEdge::WrapStart, true), (SpriteEdge::Wrap, true)].iter() {
+            let mut inter = with_quirks(|q| q.sprite_edge = *edge);
+            inter.memory[0x300] = 0x80;
+            inter.I = 0x300;
+            inter.registers[0] = 70;
+            inter.registers[1] = 33;
+            inter.execute_instruction(0xD011).unwrap();
+            assert_eq!(inter.screen[64 + 6] == 1, *drawn);
+        }
+    }
+
+    #[test]
+    fn test_quirk_sprite_wrap_start_clips_rest() {
+        let mut inter = with_quirks(|q| q.sprite_edge = SpriteEdge::WrapStart);
+        inter.memory[0x300] = 0xFF;
+        inter.I = 0x300;
+        inter.registers[0] = 124;
+        inter.registers[1] = 0;
+        inter.execute_instruction(0xD011).unwrap();
+        // Starts at 60 and the last four pixels are dropped
+        assert_eq!(inter.screen[60], 1);
+        assert_eq!(inter.screen[63], 1);
+        assert_eq!(inter.screen[64], 0);
+        assert_eq!(inter.screen[0], 0);
+    }
+
+    #[test]
+    fn test_quirk_collision_rows() {
+        let mut inter = Chip8::new(Quirks::SCHIP);
+        inter.execute_instruction(0x00FF).unwrap();
+        for i in 0..32 {
+            inter.memory[0x300 + i] = 0xFF;
+        }
+        inter.I = 0x300;
+        inter.registers[1] = 56;
+        // The bottom 8 rows of the 16x16 sprite are clipped
+        inter.execute_instruction(0xD010).unwrap();
+        assert_eq!(inter.get_vf(), 8);
+        inter.execute_instruction(0xD010).unwrap();
+        assert_eq!(inter.get_vf(), 16);
+
+        // Low resolution still only flags a collision
+        inter.execute_instruction(0x00FE).unwrap();
+        inter.registers[1] = 0;
+        inter.execute_instruction(0xD012).unwrap();
+        inter.execute_instruction(0xD012).unwrap();
+        assert_eq!(inter.get_vf(), 1);
+    }
+
+    #[test]
+    fn test_quirk_collision_rows_both_planes() {
+        let mut inter = with_quirks(|q| q.collision_rows = true);
+        inter.execute_instruction(0x00FF).unwrap();
+        inter.execute_instruction(0xF301).unwrap();
+        for i in 0..64 {
+            inter.memory[0x300 + i] = 0xFF;
+        }
+        inter.I = 0x300;
+        inter.registers[1] = 56;
+        // A row counts once however many planes it hit in
+        inter.execute_instruction(0xD010).unwrap();
+        assert_eq!(inter.get_vf(), 8);
+        inter.execute_instruction(0xD010).unwrap();
+        assert_eq!(inter.get_vf(), 16);
+    }
+
+    #[test]
+    fn test_hires_collision_without_quirk() {
+        let mut inter = Chip8::default();
+        inter.execute_instruction(0x00FF).unwrap();
+        inter.execute_instruction(0xD005).unwrap();
+        inter.execute_instruction(0xD005).unwrap();
+        assert_eq!(inter.get_vf(), 1);
+    }
+
+    #[test]
+    fn test_hires() {
+        let mut inter = Chip8::default();
+        inter.execute_instruction(0x00FF).unwrap();
+        assert!(inter.hires());
+        assert_eq!(inter.width(), 128);
+        assert_eq!(inter.height(), 64);
+        assert_eq!(inter.screen.len(), HIRES_PIXEL_COUNT);
+    }
+
+    #[test]
+    fn test_lores() {
+        let mut inter = Chip8::default();
+        inter.execute_instruction(0x00FF).unwrap();
+        inter.execute_instruction(0x00FE).unwrap();
+        assert!(!inter.hires());
+        assert_eq!(inter.width(), 64);
+        assert_eq!(inter.height(), 32);
+        assert_eq!(inter.screen.len(), PIXEL_COUNT);
+    }
+
+    #[test]
+    fn test_exit() {
+        let mut inter = Chip8::default();
+        inter.memory[0x200] = 0x00;
+        inter.memory[0x201] = 0xFD;
+        inter.run_cycle().unwrap();
+        assert!(inter.exited());
+        let pc = inter.pc;
+        inter.run_cycle().unwrap();
+        assert_eq!(inter.pc, pc);
+    }
+
+    #[test]
+    fn test_scroll_down() {
+        let mut inter = Chip8::default();
+        inter.screen[3] = 1;
+        inter.execute_instruction(0x00C2).unwrap();
+        assert_eq!(inter.screen[3], 0);
+        assert_eq!(inter.screen[2 * 64 + 3], 1);
+    }
+
+    #[test]
+    fn test_scroll_right() {
+        let mut inter = Chip8::default();
+        inter.screen[3] = 1;
+        inter.screen[63] = 1;
+        inter.execute_instruction(0x00FB).unwrap();
+        assert_eq!(inter.screen[3], 0);
+        assert_eq!(inter.screen[7], 1);
+        assert_eq!(inter.screen.iter().sum::<u8>(), 1);
+    }
+
+    #[test]
+    fn test_scroll_left() {
+        let mut inter = Chip8::default();
+        inter.screen[1] = 1;
+        inter.screen[7] = 1;
+        inter.execute_instruction(0x00FC).unwrap();
+        assert_eq!(inter.screen[3], 1);
+        assert_eq!(inter.screen.iter().sum::<u8>(), 1);
+    }
+
+    #[test]
+    fn test_draw_big_sprite() {
+        let mut inter = Chip8::default();
+        inter.execute_instruction(0x00FF).unwrap();
+        for i in 0..32 {
+            inter.memory[0x300 + i] = 0xFF;
+        }
+        inter.I = 0x300;
+        inter.registers[0] = 100;
+        inter.registers[1] = 10;
+        inter.execute_instruction(0xD010).unwrap();
+        assert_eq!(inter.screen.iter().map(|p| *p as usize).sum::<usize>(), 256);
+        assert_eq!(inter.screen[10 * 128 + 100], 1);
+        assert_eq!(inter.screen[25 * 128 + 115], 1);
+        assert_eq!(inter.screen[26 * 128 + 115], 0);
+        assert_eq!(inter.get_vf(), 0);
+    }
+
+    #[test]
+    fn test_draw_collision() {
+        let mut inter = Chip8::default();
+        inter.memory[0x300] = 0x80;
+        inter.I = 0x300;
+        inter.execute_instruction(0xD011).unwrap();
+        assert_eq!(inter.get_vf(), 0);
+        inter.execute_instruction(0xD011).unwrap();
+        assert_eq!(inter.get_vf(), 1);
+        assert_eq!(inter.screen[0], 0);
+    }
+
+    #[test]
+    fn test_set_I_to_big_char() {
+        let mut inter = Chip8::default();
+        inter.registers[1] = 3;
+        inter.execute_instruction(0xF130).unwrap();
+        assert_eq!(inter.I as usize, BIG_FONT_ADDR + 30);
+        assert_eq!(inter.memory[inter.I as usize], BIG_FONTSET[30]);
+    }
+
+    #[test]
+    fn test_rpl_flags() {
+        let mut inter = Chip8::default();
+        for i in 0..4usize {
+            inter.registers[i] = i as u8 + 1;
+        }
+        inter.execute_instruction(0xF375).unwrap();
+        for i in 0..4usize {
+            inter.registers[i] = 0;
+        }
+        inter.execute_instruction(0xF285).unwrap();
+        assert_eq!(inter.registers[0..4], [1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn test_large_memory() {
+        let inter = Chip8::new(Quirks::XOCHIP);
+        assert_eq!(inter.memory.len(), 0x10000);
+        assert_eq!(Chip8::default().memory.len(), 4096);
+    }
+
+    #[test]
+    fn test_load_long_I() {
+        let mut inter = Chip8::new(Quirks::XOCHIP);
+        inter.memory[0x202] = 0xAB;
+        inter.memory[0x203] = 0xCD;
+        inter.execute_instruction(0xF000).unwrap();
+        assert_eq!(inter.I, 0xABCD);
+        assert_eq!(inter.pc, 0x204);
+    }
+
+    #[test]
+    fn test_skip_long_instruction() {
+        let mut inter = Chip8::new(Quirks::XOCHIP);
+        inter.memory[0x202] = 0xF0;
+        inter.memory[0x203] = 0x00;
+        inter.execute_instruction(0x3000).unwrap();
+        assert_eq!(inter.pc, 0x206);
+    }
+
+    #[test]
+    fn test_save_range() {
+        let mut inter = Chip8::default();
+        inter.I = 0x300;
+        for i in 0..5usize {
+            inter.registers[i] = i as u8 + 1;
+        }
+        inter.execute_instruction(0x5132).unwrap();
+        assert_eq!(inter.memory[0x300..0x304], [2, 3, 4, 0]);
+        assert_eq!(inter.I, 0x300);
+    }
+
+    #[test]
+    fn test_save_range_reversed() {
+        let mut inter = Chip8::default();
+        inter.I = 0x300;
+        for i in 0..5usize {
+            inter.registers[i] = i as u8 + 1;
+        }
+        inter.execute_instruction(0x5312).unwrap();
+        assert_eq!(inter.memory[0x300..0x303], [4, 3, 2]);
+    }
+
+    #[test]
+    fn test_load_range() {
+        let mut inter = Chip8::default();
+        inter.I = 0x300;
+        inter.memory[0x300] = 7;
+        inter.memory[0x301] = 8;
+        inter.execute_instruction(0x5453).unwrap();
+        assert_eq!(inter.registers[4], 7);
+        assert_eq!(inter.registers[5], 8);
+        assert_eq!(inter.registers[6], 0);
+        assert_eq!(inter.I, 0x300);
+    }
+
+    #[test]
+    fn test_select_planes() {
+        let mut inter = Chip8::default();
+        inter.execute_instruction(0xF301).unwrap();
+        assert_eq!(inter.plane, 3);
+    }
+
+    #[test]
+    fn test_draw_second_plane() {
+        let mut inter = Chip8::default();
+        inter.memory[0x300] = 0x80;
+        inter.I = 0x300;
+        inter.execute_instruction(0xF201).unwrap();
+        inter.execute_instruction(0xD011).unwrap();
+        assert_eq!(inter.screen[0], 2);
+    }
+
+    #[test]
+    fn test_draw_both_planes() {
+        let mut inter = Chip8::default();
+        inter.memory[0x300] = 0x80;
+        inter.memory[0x301] = 0xC0;
+        inter.I = 0x300;
+        inter.execute_instruction(0xF301).unwrap();
+        inter.execute_instruction(0xD011).unwrap();
+        assert_eq!(inter.screen[0], 3);
+        assert_eq!(inter.screen[1], 2);
+    }
+
+    #[test]
+    fn test_clear_selected_plane() {
+        let mut inter = Chip8::default();
+        inter.screen = vec![3; PIXEL_COUNT];
+        inter.execute_instruction(0xF201).unwrap();
+        inter.execute_instruction(0x00E0).unwrap();
+        assert!(inter.screen.iter().all(|p| *p == 1));
+    }
+
+    #[test]
+    fn test_scroll_selected_plane() {
+        let mut inter = Chip8::default();
+        inter.screen[64] = 3;
+        inter.execute_instruction(0xF101).unwrap();
+        inter.execute_instruction(0x00D1).unwrap();
+        assert_eq!(inter.screen[0], 1);
+        assert_eq!(inter.screen[64], 2);
+    }
+
+    #[test]
+    fn test_load_audio_pattern() {
+        let mut inter = Chip8::default();
+        inter.I = 0x300;
+        for i in 0..16usize {
+            inter.memory[0x300 + i] = i as u8;
+        }
+        inter.execute_instruction(0xF002).unwrap();
+        assert_eq!(inter.audio_pattern()[15], 15);
+        assert!(inter.uses_audio_pattern());
+    }
+
+    #[test]
+    fn test_pitch() {
+        let mut inter = Chip8::default();
+        assert_eq!(inter.pattern_rate(), 4000.0);
+        inter.registers[2] = 112;
+        inter.execute_instruction(0xF23A).unwrap();
+        assert_eq!(inter.pattern_rate(), 8000.0);
+    }
+
+    #[test]
+    fn test_invalid_opcode() {
+        let mut inter = Chip8::default();
+        match inter.execute_instruction(0xE1FF) {
+            Err(EmuError::InvalidOpcode { pc, opcode }) => {
+                assert_eq!(pc, 0x200);
+                assert_eq!(opcode, 0xE1FF);
+            },
+            _ => panic!("expected an invalid opcode"),
+        }
+    }
+
+    #[test]
+    fn test_register_skips_need_a_zero_nibble() {
+        use crate::hardware::instruction::Op;
+        for &opcode in [0x5121, 0x9121].iter() {
+            let mut inter = Chip8::default();
+            assert_eq!(Instruction::new(opcode).decode(), Op::Unknown(opcode));
+            assert!(matches!(inter.execute_instruction(opcode),
+                Err(EmuError::InvalidOpcode { opcode: found, .. }) if found == opcode));
+            assert_eq!(inter.pc, 0x200);
+        }
+    }
+
+    #[test]
+    fn test_stack_underflow() {
+        let mut inter = Chip8::default();
+        assert!(matches!(inter.execute_instruction(0x00EE), Err(EmuError::StackUnderflow)));
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        let mut inter = Chip8::default();
+        for _ in 0..STACK_SIZE {
+            inter.execute_instruction(0x2300).unwrap();
+        }
+        assert!(matches!(inter.execute_instruction(0x2300), Err(EmuError::StackOverflow)));
+    }
+
+    #[test]
+    fn test_store_out_of_bounds() {
+        let mut inter = Chip8::default();
+        inter.I = 0xFFE;
+        match inter.execute_instruction(0xF355) {
+            Err(EmuError::MemoryOutOfBounds { addr }) => assert_eq!(addr, 0x1000),
+            _ => panic!("expected an out of bounds access"),
+        }
+    }
+
+    #[test]
+    fn test_draw_out_of_bounds() {
+        let mut inter = Chip8::default();
+        inter.I = 0xFFF;
+        assert!(matches!(inter.execute_instruction(0xD015),
+            Err(EmuError::MemoryOutOfBounds { .. })));
+    }
+
+    #[test]
+    fn test_draw_at_end_of_large_memory() {
+        // Both planes of a 16x16 sprite end exactly at the top of 64 KiB
+        let mut inter = Chip8::new(Quirks::XOCHIP);
+        inter.execute_instruction(0xF301).unwrap();
+        inter.I = 0xFFC0;
+        inter.memory[0xFFFF] = 0x01;
+        inter.execute_instruction(0xD000).unwrap();
+        assert_eq!(inter.screen[15 * 64 + 15], 2);
+
+        inter.I = 0xFFC1;
+        assert!(matches!(inter.execute_instruction(0xD000),
+            Err(EmuError::MemoryOutOfBounds { .. })));
+    }
+
+    #[test]
+    fn test_fetch_out_of_bounds() {
+        let mut inter = Chip8::default();
+        inter.pc = 0xFFF;
+        assert!(matches!(inter.run_cycle(), Err(EmuError::MemoryOutOfBounds { .. })));
+    }
+
+    #[test]
+    fn test_bus_wrap() {
+        let mut inter = Chip8::default();
+        inter.set_bus_policy(BusPolicy::Wrap);
+        inter.registers[0] = 1;
+        inter.registers[1] = 2;
+        inter.I = 0xFFF;
+        inter.execute_instruction(0xF155).unwrap();
+        assert_eq!(inter.memory[0xFFF], 1);
+        assert_eq!(inter.memory[0], 2);
+
+        // The opcode straddles the end of memory, 6xxx then the font's F0
+        inter.memory[0xFFF] = 0x63;
+        inter.pc = 0xFFF;
+        inter.run_cycle().unwrap();
+        assert_eq!(inter.registers[3], 0x02);
+    }
+
+    #[test]
+    fn test_written_includes_unchanged_bytes() {
+        let mut inter = Chip8::default();
+        inter.I = 0x300;
+        inter.execute_instruction(0xF155).unwrap();
+        assert_eq!(inter.written(), &[0x300, 0x301]);
+        inter.execute_instruction(0x6000).unwrap();
+        assert!(inter.written().is_empty());
+    }
+
+    #[test]
+    fn test_bus_log() {
+        let mut inter = Chip8::default();
+        inter.set_bus_policy(BusPolicy::Log);
+        inter.registers[0] = 123;
+        inter.I = 0xFFE;
+        inter.execute_instruction(0xF033).unwrap();
+        assert_eq!(inter.memory[0xFFE], 1);
+        assert_eq!(inter.memory[0xFFF], 2);
+
+        inter.registers[0] = 9;
+        inter.I = 0xFFF;
+        inter.execute_instruction(0xF165).unwrap();
+        assert_eq!(inter.registers[0], 2);
+        assert_eq!(inter.registers[1], 0);
+        assert_eq!(inter.take_faults(), Some(Faults { count: 2, last: 0x1000 }));
+    }
+
+    #[test]
+    fn test_skip_at_end_of_memory_is_not_a_fault() {
+        let mut inter = Chip8::default();
+        inter.set_bus_policy(BusPolicy::Log);
+        inter.pc = 0xFFE;
+        inter.execute_instruction(0x3000).unwrap();
+        assert_eq!(inter.pc, 0x1002);
+        assert_eq!(inter.take_faults(), None);
+
+        inter.set_bus_policy(BusPolicy::Error);
+        inter.pc = 0xFFE;
+        inter.execute_instruction(0x3000).unwrap();
+        assert_eq!(inter.current_opcode(), None);
+    }
+
+    #[test]
+    fn test_save_state_bus_policy() {
+        let mut inter = Chip8::default();
+        inter.set_bus_policy(BusPolicy::Wrap);
+        let state = inter.save_state();
+        let mut restored = Chip8::default();
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.bus_policy(), BusPolicy::Wrap);
+    }
+
+    #[test]
+    fn test_jump_to_zero() {
+        let mut inter = Chip8::default();
+        inter.execute_instruction(0x1000).unwrap();
+        assert_eq!(inter.pc, 0);
+    }
+
+    #[test]
+    fn test_load_program_missing() {
+        let mut inter = Chip8::default();
+        assert!(matches!(inter.load_program("programs/does-not-exist"), Err(EmuError::Io(_))));
+    }
+
+    #[test]
+    fn test_load_program_too_large() {
+        let path = std::env::temp_dir().join("chip8-rs-too-large.ch8");
+        std::fs::write(&path, vec![0; 4096]).unwrap();
+        let mut inter = Chip8::default();
+        let result = inter.load_program(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(EmuError::RomTooLarge { size: 4096, max: 3584 })));
+    }
+
+    #[test]
+    fn test_load_program() {
+        let mut inter = Chip8::default();
+        inter.load_program("programs/PONG").unwrap();
+        assert_eq!(inter.memory[0x200], 0x6A);
+    }
+
+    #[test]
+    fn test_register_dump() {
+        let mut inter = Chip8::default();
+        inter.registers[0xA] = 0x1F;
+        let dump = inter.register_dump();
+        assert!(dump.starts_with("PC 0200 I 0000 SP 0\n"));
+        assert!(dump.contains("VA 1F"));
+    }
+
+    #[test]
+    fn test_run_cycle_leaves_timers() {
+        let mut inter = Chip8::default();
+        inter.memory[0x200] = 0x60;
+        inter.dt = 5;
+        inter.run_cycle().unwrap();
+        assert_eq!(inter.dt, 5);
+    }
+
+    #[test]
+    fn test_step_frame() {
+        let mut inter = Chip8::default();
+        // Add 1 to V0 forever
+        for i in 0..20 {
+            inter.memory[0x200 + i * 2] = 0x70;
+            inter.memory[0x201 + i * 2] = 0x01;
+        }
+        inter.set_cycles_per_frame(7);
+        inter.dt = 5;
+        inter.st = 5;
+        inter.step_frame().unwrap();
+        assert_eq!(inter.registers[0], 7);
+        assert_eq!(inter.pc, 0x200 + 14);
+        assert_eq!(inter.dt, 4);
+        assert_eq!(inter.st, 4);
+    }
+
+    #[test]
+    fn test_step_frame_timers_stop_at_zero() {
+        let mut inter = Chip8::default();
+        inter.dt = 2;
+        inter.memory[0x200] = 0x12;
+        inter.memory[0x201] = 0x00;
+        for _ in 0..3 {
+            inter.step_frame().unwrap();
+        }
+        assert_eq!(inter.dt, 0);
+    }
+
+    #[test]
+    fn test_step_frame_exit() {
+        let mut inter = Chip8::default();
+        inter.memory[0x200] = 0x00;
+        inter.memory[0x201] = 0xFD;
+        inter.step_frame().unwrap();
+        assert!(inter.exited());
+        assert_eq!(inter.pc, 0x202);
+    }
+
+    #[test]
+    fn test_sound_active() {
+        let mut inter = Chip8::default();
+        assert!(!inter.sound_active());
+        inter.registers[1] = 2;
+        inter.execute_instruction(0xF118).unwrap();
+        assert!(inter.sound_active());
+        inter.tick_timers();
+        assert!(inter.sound_active());
+        inter.tick_timers();
+        assert!(!inter.sound_active());
+    }
+
+    #[test]
+    fn test_inspection() {
+        let mut inter = Chip8::new(Quirks::VIP);
+        inter.memory[0x200] = 0x22;
+        inter.memory[0x201] = 0x08;
+        inter.registers[3] = 9;
+        inter.I = 0x345;
+        inter.dt = 4;
+        inter.st = 6;
+        assert_eq!(inter.current_opcode(), Some(0x2208));
+        inter.run_cycle().unwrap();
+        assert_eq!(inter.pc(), 0x208);
+        assert_eq!(inter.index(), 0x345);
+        assert_eq!(inter.registers()[3], 9);
+        assert_eq!(inter.stack(), &[0x200]);
+        assert_eq!(inter.memory()[0x201], 0x08);
+        assert_eq!(inter.delay_timer(), 4);
+        assert_eq!(inter.sound_timer(), 6);
+        assert_eq!(inter.quirks(), Quirks::VIP);
+    }
+
+    #[test]
+    fn test_current_opcode_out_of_bounds() {
+        let mut inter = Chip8::default();
+        inter.pc = 0xFFF;
+        assert_eq!(inter.current_opcode(), None);
+    }
+
+    #[test]
+    fn test_random_seeded() {
+        let mut a = Chip8::default();
+        let mut b = Chip8::default();
+        a.set_seed(1234);
+        b.set_seed(1234);
+        let mut expected = Rng::new(1234);
+        for _ in 0..8 {
+            a.execute_instruction(0xC1FF).unwrap();
+            b.execute_instruction(0xC1FF).unwrap();
+            assert_eq!(a.registers[1], expected.next_u8());
+            assert_eq!(a.registers[1], b.registers[1]);
+        }
+    }
+
+    #[test]
+    fn test_random_masked() {
+        let mut inter = Chip8::default();
+        inter.set_seed(99);
+        let mut expected = Rng::new(99);
+        inter.execute_instruction(0xC20F).unwrap();
+        assert_eq!(inter.registers[2], expected.next_u8() & 0x0F);
+    }
+
+    #[test]
+    fn test_save_state_keeps_rng() {
+        let mut inter = running_state();
+        inter.set_seed(5);
+        let state = inter.save_state();
+        inter.execute_instruction(0xC3FF).unwrap();
+        let first = inter.registers[3];
+
+        inter.set_seed(6);
+        inter.load_state(&state).unwrap();
+        inter.execute_instruction(0xC3FF).unwrap();
+        assert_eq!(inter.registers[3], first);
+    }
+
+    // Four sprites drawn one after another, then a loop
+    const FOUR_SPRITES: [u8; 10] = [0xD0, 0x05, 0xD0, 0x05, 0xD0, 0x05, 0xD0, 0x05, 0x12, 0x08];
+
+    #[test]
+    fn test_display_wait_one_sprite_per_frame() {
+        let mut quirks = Quirks::default();
+        quirks.display_wait = true;
+        let mut inter = Chip8::new(quirks);
+        inter.load_rom(&FOUR_SPRITES).unwrap();
+
+        // step_frame is the frame clock, the first DXYN waits out frame 0
+        inter.step_frame().unwrap();
+        assert_eq!(inter.pc(), 0x200);
+        assert_eq!(inter.screen[0], 0);
+
+        for frame in 1..=4 {
+            inter.step_frame().unwrap();
+            assert_eq!(inter.pc(), 0x200 + 2 * frame);
+            assert_eq!(inter.screen[0], (frame % 2) as u8);
+        }
+        inter.step_frame().unwrap();
+        assert_eq!(inter.pc(), 0x208);
+    }
+
+    #[test]
+    fn test_display_wait_stalls_cycles() {
+        let mut quirks = Quirks::default();
+        quirks.display_wait = true;
+        let mut inter = Chip8::new(quirks);
+        inter.load_rom(&FOUR_SPRITES).unwrap();
+        for _ in 0..100 {
+            inter.run_cycle().unwrap();
+        }
+        assert_eq!(inter.pc(), 0x200);
+
+        inter.tick_timers();
+        inter.run_cycle().unwrap();
+        inter.run_cycle().unwrap();
+        assert_eq!(inter.pc(), 0x202);
+        assert_eq!(inter.screen[0], 1);
+    }
+
+    #[test]
+    fn test_no_display_wait() {
+        let mut inter = Chip8::default();
+        inter.load_rom(&FOUR_SPRITES).unwrap();
+        inter.step_frame().unwrap();
+        assert_eq!(inter.pc(), 0x208);
+        assert_eq!(inter.screen[0], 0);
+    }
+
+    #[test]
+    fn test_save_state_keeps_display_wait() {
+        let mut inter = Chip8::new(Quirks::VIP);
+        inter.load_rom(&FOUR_SPRITES).unwrap();
+        inter.run_cycle().unwrap();
+        let state = inter.save_state();
+
+        let mut restored = Chip8::new(Quirks::VIP);
+        restored.load_rom(&FOUR_SPRITES).unwrap();
+        restored.load_state(&state).unwrap();
+        restored.run_cycle().unwrap();
+        assert_eq!(restored.pc(), 0x200);
+        restored.tick_timers();
+        restored.run_cycle().unwrap();
+        assert_eq!(restored.pc(), 0x202);
+    }
+
+    #[test]
+    fn test_save_state_keeps_key_wait() {
+        let mut inter = Chip8::new(Quirks::VIP);
+        inter.load_rom(&[0xF1, 0x0A]).unwrap();
+        inter.run_cycle().unwrap();
+        inter.key_down(0xF);
+        inter.run_cycle().unwrap();
+        let state = inter.save_state();
+
+        let mut restored = Chip8::new(Quirks::VIP);
+        restored.load_rom(&[0xF1, 0x0A]).unwrap();
+        restored.load_state(&state).unwrap();
+        restored.key_up(0xF);
+        restored.run_cycle().unwrap();
+        assert_eq!(restored.pc(), 0x202);
+        assert_eq!(restored.registers()[1], 0xF);
+    }
+
+    fn running_state() -> Chip8 {
+        let mut inter = Chip8::new(Quirks::SCHIP);
+        inter.load_rom(&[0x00, 0xFF, 0x22, 0x06, 0x12, 0x02, 0xA2, 0x00, 0xD0, 0x01]).unwrap();
+        inter.registers[0xA] = 7;
+        inter.key_down(0xF);
+        inter.dt = 30;
+        inter.rpl[2] = 5;
+        for _ in 0..4 {
+            inter.run_cycle().unwrap();
+        }
+        return inter;
+    }
+
+    #[test]
+    fn test_save_state_round_trip() {
+        let inter = running_state();
+        let state = inter.save_state();
+
+        let mut restored = Chip8::default();
+        restored.load_rom(&[0x00, 0xFF, 0x22, 0x06, 0x12, 0x02, 0xA2, 0x00, 0xD0, 0x01]).unwrap();
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.save_state(), state);
+        assert_eq!(restored.quirks(), Quirks::SCHIP);
+        assert!(restored.hires());
+        assert_eq!(restored.pc(), inter.pc());
+        assert_eq!(restored.index(), 0x200);
+        assert_eq!(restored.stack(), inter.stack());
+        assert_eq!(restored.registers()[0xA], 7);
+        assert_eq!(restored.delay_timer(), 30);
+        assert_eq!(restored.keys[0xF], 1);
+        assert_eq!(restored.screen, inter.screen);
+        assert!(restored.draw);
+    }
+
+    #[test]
+    fn test_load_state_rom_mismatch() {
+        let state = running_state().save_state();
+        let mut other = Chip8::new(Quirks::SCHIP);
+        other.load_rom(&[0x00, 0xE0]).unwrap();
+        assert!(matches!(other.load_state(&state), Err(EmuError::StateRomMismatch)));
+        assert_eq!(other.pc(), 0x200);
+    }
+
+    #[test]
+    fn test_load_state_version() {
+        let inter = running_state();
+        let mut state = inter.save_state();
+        state[5] = state[5].wrapping_add(1);
+        let mut restored = running_state();
+        match restored.load_state(&state) {
+            Err(EmuError::StateVersion { expected, .. }) => assert_eq!(expected, savestate::VERSION),
+            other => panic!("expected a version error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_load_state_corrupt() {
+        let mut inter = running_state();
+        let state = inter.save_state();
+        assert!(matches!(inter.load_state(&state[..state.len() - 1]), Err(EmuError::BadState)));
+        assert!(matches!(inter.load_state(b"not a state"), Err(EmuError::BadState)));
+        let mut longer = state.clone();
+        longer.push(0);
+        assert!(matches!(inter.load_state(&longer), Err(EmuError::BadState)));
+    }
 }
@@ -0,0 +1,61 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Faults the interpreter can hit while loading or running a program.
+#[derive(Debug)]
+pub enum EmuError {
+    InvalidOpcode { pc: u16, opcode: u16 },
+    StackOverflow,
+    StackUnderflow,
+    MemoryOutOfBounds { addr: usize },
+    RomTooLarge { size: usize, max: usize },
+    /// Not a save state, or one that's truncated or corrupt
+    BadState,
+    StateVersion { found: u16, expected: u16 },
+    /// The save state was made with a different ROM
+    StateRomMismatch,
+    /// A movie file that can't be played
+    BadMovie(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for EmuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            EmuError::InvalidOpcode { pc, opcode } => {
+                write!(f, "invalid opcode {:04X} at {:04X}", opcode, pc)
+            },
+            EmuError::StackOverflow => write!(f, "stack overflow"),
+            EmuError::StackUnderflow => write!(f, "return with an empty stack"),
+            EmuError::MemoryOutOfBounds { addr } => {
+                write!(f, "memory access out of bounds at {:04X}", addr)
+            },
+            EmuError::RomTooLarge { size, max } => {
+                write!(f, "ROM is {} bytes, the most that fits is {}", size, max)
+            },
+            EmuError::BadState => write!(f, "not a valid save state"),
+            EmuError::StateVersion { found, expected } => {
+                write!(f, "save state is version {}, this build reads version {}", found, expected)
+            },
+            EmuError::StateRomMismatch => write!(f, "save state was made with a different ROM"),
+            EmuError::BadMovie(reason) => write!(f, "{}", reason),
+            EmuError::Io(e) => write!(f, "{}", e),
+        };
+    }
+}
+
+impl Error for EmuError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        return match self {
+            EmuError::Io(e) => Some(e),
+            _ => None,
+        };
+    }
+}
+
+impl From<io::Error> for EmuError {
+    fn from(e: io::Error) -> Self {
+        return EmuError::Io(e);
+    }
+}
@@ -1,4 +1,5 @@
 
+use std::fmt;
 
 pub const OPCODE_LEN: u16 = 2;
 
@@ -6,6 +7,151 @@ pub struct Instruction {
     opcode: u16
 }
 
+/// A decoded instruction. Register operands are register numbers, so
+/// `Drw(1, 2, 5)` is `DRW V1, V2, 5`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    Sys(u16),
+    Cls,
+    Ret,
+    Jp(u16),
+    Call(u16),
+    SeByte(u8, u8),
+    SneByte(u8, u8),
+    SeReg(u8, u8),
+    LdByte(u8, u8),
+    AddByte(u8, u8),
+    LdReg(u8, u8),
+    Or(u8, u8),
+    And(u8, u8),
+    Xor(u8, u8),
+    AddReg(u8, u8),
+    Sub(u8, u8),
+    Shr(u8, u8),
+    Subn(u8, u8),
+    Shl(u8, u8),
+    SneReg(u8, u8),
+    LdI(u16),
+    JpV0(u16),
+    Rnd(u8, u8),
+    Drw(u8, u8, u8),
+    Skp(u8),
+    Sknp(u8),
+    LdVxDt(u8),
+    LdVxK(u8),
+    LdDtVx(u8),
+    LdStVx(u8),
+    AddI(u8),
+    LdF(u8),
+    LdB(u8),
+    LdIVx(u8),
+    LdVxI(u8),
+    // SUPER-CHIP
+    Scd(u8),
+    Scr,
+    Scl,
+    Exit,
+    Low,
+    High,
+    LdHf(u8),
+    LdRVx(u8),
+    LdVxR(u8),
+    // XO-CHIP
+    Scu(u8),
+    SaveRange(u8, u8),
+    LoadRange(u8, u8),
+    /// F000 NNNN, the address is the word following the opcode
+    LdILong,
+    Plane(u8),
+    Audio,
+    Pitch(u8),
+    Unknown(u16),
+}
+
+impl Op {
+    /// Size of the instruction in bytes
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> u16 {
+        return match self {
+            Op::LdILong => OPCODE_LEN * 2,
+            _ => OPCODE_LEN,
+        };
+    }
+
+    /// Whether this skips the following instruction on some condition
+    pub fn is_skip(&self) -> bool {
+        return matches!(self,
+            Op::SeByte(..) | Op::SneByte(..) | Op::SeReg(..) | Op::SneReg(..) | Op::Skp(_) | Op::Sknp(_));
+    }
+
+    /// Encode back into an opcode, for `LdILong` this is just the first word
+    pub fn encode(&self) -> u16 {
+        let xy = |op: u16, x: u8, y: u8, n: u16| op | (x as u16) << 8 | (y as u16) << 4 | n;
+        let xkk = |op: u16, x: u8, kk: u8| op | (x as u16) << 8 | kk as u16;
+        return match *self {
+            Op::Sys(addr) => addr & 0x0FFF,
+            Op::Cls => 0x00E0,
+            Op::Ret => 0x00EE,
+            Op::Jp(addr) => 0x1000 | addr & 0x0FFF,
+            Op::Call(addr) => 0x2000 | addr & 0x0FFF,
+            Op::SeByte(x, kk) => xkk(0x3000, x, kk),
+            Op::SneByte(x, kk) => xkk(0x4000, x, kk),
+            Op::SeReg(x, y) => xy(0x5000, x, y, 0),
+            Op::LdByte(x, kk) => xkk(0x6000, x, kk),
+            Op::AddByte(x, kk) => xkk(0x7000, x, kk),
+            Op::LdReg(x, y) => xy(0x8000, x, y, 0),
+            Op::Or(x, y) => xy(0x8000, x, y, 1),
+            Op::And(x, y) => xy(0x8000, x, y, 2),
+            Op::Xor(x, y) => xy(0x8000, x, y, 3),
+            Op::AddReg(x, y) => xy(0x8000, x, y, 4),
+            Op::Sub(x, y) => xy(0x8000, x, y, 5),
+            Op::Shr(x, y) => xy(0x8000, x, y, 6),
+            Op::Subn(x, y) => xy(0x8000, x, y, 7),
+            Op::Shl(x, y) => xy(0x8000, x, y, 0xE),
+            Op::SneReg(x, y) => xy(0x9000, x, y, 0),
+            Op::LdI(addr) => 0xA000 | addr & 0x0FFF,
+            Op::JpV0(addr) => 0xB000 | addr & 0x0FFF,
+            Op::Rnd(x, kk) => xkk(0xC000, x, kk),
+            Op::Drw(x, y, n) => xy(0xD000, x, y, n as u16 & 0xF),
+            Op::Skp(x) => xkk(0xE000, x, 0x9E),
+            Op::Sknp(x) => xkk(0xE000, x, 0xA1),
+            Op::LdVxDt(x) => xkk(0xF000, x, 0x07),
+            Op::LdVxK(x) => xkk(0xF000, x, 0x0A),
+            Op::LdDtVx(x) => xkk(0xF000, x, 0x15),
+            Op::LdStVx(x) => xkk(0xF000, x, 0x18),
+            Op::AddI(x) => xkk(0xF000, x, 0x1E),
+            Op::LdF(x) => xkk(0xF000, x, 0x29),
+            Op::LdB(x) => xkk(0xF000, x, 0x33),
+            Op::LdIVx(x) => xkk(0xF000, x, 0x55),
+            Op::LdVxI(x) => xkk(0xF000, x, 0x65),
+            Op::Scd(n) => 0x00C0 | n as u16 & 0xF,
+            Op::Scr => 0x00FB,
+            Op::Scl => 0x00FC,
+            Op::Exit => 0x00FD,
+            Op::Low => 0x00FE,
+            Op::High => 0x00FF,
+            Op::LdHf(x) => xkk(0xF000, x, 0x30),
+            Op::LdRVx(x) => xkk(0xF000, x, 0x75),
+            Op::LdVxR(x) => xkk(0xF000, x, 0x85),
+            Op::Scu(n) => 0x00D0 | n as u16 & 0xF,
+            Op::SaveRange(x, y) => xy(0x5000, x, y, 2),
+            Op::LoadRange(x, y) => xy(0x5000, x, y, 3),
+            Op::LdILong => 0xF000,
+            Op::Plane(n) => xkk(0xF000, n, 0x01),
+            Op::Audio => 0xF002,
+            Op::Pitch(x) => xkk(0xF000, x, 0x3A),
+            Op::Unknown(opcode) => opcode,
+        };
+    }
+}
+
+impl fmt::Display for Op {
+    /// Cowgod style mnemonic with plain hex addresses
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{}", crate::disasm::format_cowgod(self, &|addr| format!("{:#05X}", addr)));
+    }
+}
+
 
 impl Instruction {
     pub fn new(code: u16) -> Self {
@@ -37,45 +183,154 @@ impl Instruction {
     pub fn get_top_nib(&self) -> u8 {
         return ((self.opcode & 0xF000) >> 12) as u8;
     }
+
+    pub fn decode(&self) -> Op {
+        let x = self.get_x();
+        let y = self.get_y();
+        let n = self.get_nibble();
+        let kk = self.get_kk();
+        let addr = self.get_addr();
+
+        return match self.get_top_nib() {
+            0 => match self.opcode {
+                0x00E0 => Op::Cls,
+                0x00EE => Op::Ret,
+                0x00C0..=0x00CF => Op::Scd(n),
+                0x00D0..=0x00DF => Op::Scu(n),
+                0x00FB => Op::Scr,
+                0x00FC => Op::Scl,
+                0x00FD => Op::Exit,
+                0x00FE => Op::Low,
+                0x00FF => Op::High,
+                _ => Op::Sys(addr),
+            },
+            1 => Op::Jp(addr),
+            2 => Op::Call(addr),
+            3 => Op::SeByte(x, kk),
+            4 => Op::SneByte(x, kk),
+            5 => match n {
+                0 => Op::SeReg(x, y),
+                2 => Op::SaveRange(x, y),
+                3 => Op::LoadRange(x, y),
+                _ => Op::Unknown(self.opcode),
+            },
+            6 => Op::LdByte(x, kk),
+            7 => Op::AddByte(x, kk),
+            8 => match n {
+                0 => Op::LdReg(x, y),
+                1 => Op::Or(x, y),
+                2 => Op::And(x, y),
+                3 => Op::Xor(x, y),
+                4 => Op::AddReg(x, y),
+                5 => Op::Sub(x, y),
+                6 => Op::Shr(x, y),
+                7 => Op::Subn(x, y),
+                0xE => Op::Shl(x, y),
+                _ => Op::Unknown(self.opcode),
+            },
+            9 if n == 0 => Op::SneReg(x, y),
+            0xA => Op::LdI(addr),
+            0xB => Op::JpV0(addr),
+            0xC => Op::Rnd(x, kk),
+            0xD => Op::Drw(x, y, n),
+            0xE => match kk {
+                0x9E => Op::Skp(x),
+                0xA1 => Op::Sknp(x),
+                _ => Op::Unknown(self.opcode),
+            },
+            0xF => match kk {
+                0x00 if x == 0 => Op::LdILong,
+                0x01 => Op::Plane(x),
+                0x02 if x == 0 => Op::Audio,
+                0x07 => Op::LdVxDt(x),
+                0x0A => Op::LdVxK(x),
+                0x15 => Op::LdDtVx(x),
+                0x18 => Op::LdStVx(x),
+                0x1E => Op::AddI(x),
+                0x29 => Op::LdF(x),
+                0x30 => Op::LdHf(x),
+                0x33 => Op::LdB(x),
+                0x3A => Op::Pitch(x),
+                0x55 => Op::LdIVx(x),
+                0x65 => Op::LdVxI(x),
+                0x75 => Op::LdRVx(x),
+                0x85 => Op::LdVxR(x),
+                _ => Op::Unknown(self.opcode),
+            },
+            _ => Op::Unknown(self.opcode),
+        };
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Instruction;
+    use super::{Instruction, Op};
 
     #[test]
     fn test_get_nibble() {
-        let instruction = Instruction::new(0x4739 as u16);
-        assert_eq!(instruction.get_nibble(), 9 as u8);
+        let instruction = Instruction::new(0x4739);
+        assert_eq!(instruction.get_nibble(), 9);
     }
 
     #[test]
     fn test_get_addr() {
-        let instruction = Instruction::new(0x4739 as u16);
-        assert_eq!(instruction.get_addr(), 0x0739 as u16);
+        let instruction = Instruction::new(0x4739);
+        assert_eq!(instruction.get_addr(), 0x0739);
     }
 
     #[test]
     fn test_get_x() {
-        let instruction = Instruction::new(0x4739 as u16);
-        assert_eq!(instruction.get_x(), 7 as u8);
+        let instruction = Instruction::new(0x4739);
+        assert_eq!(instruction.get_x(), 7);
     }
 
     #[test]
     fn test_get_y() {
-        let instruction = Instruction::new(0x4739 as u16);
-        assert_eq!(instruction.get_y(), 3 as u8);
+        let instruction = Instruction::new(0x4739);
+        assert_eq!(instruction.get_y(), 3);
     }
 
     #[test]
     fn test_get_kk() {
-        let instruction = Instruction::new(0x4739 as u16);
-        assert_eq!(instruction.get_kk(), 0x39 as u8);
+        let instruction = Instruction::new(0x4739);
+        assert_eq!(instruction.get_kk(), 0x39);
     }
 
     #[test]
     fn test_get_top_nib() {
-        let instruction = Instruction::new(0x4739 as u16);
-        assert_eq!(instruction.get_top_nib(), 4 as u8);
+        let instruction = Instruction::new(0x4739);
+        assert_eq!(instruction.get_top_nib(), 4);
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(Instruction::new(0x00E0).decode(), Op::Cls);
+        assert_eq!(Instruction::new(0x1234).decode(), Op::Jp(0x234));
+        assert_eq!(Instruction::new(0xD125).decode(), Op::Drw(1, 2, 5));
+        assert_eq!(Instruction::new(0x8AB6).decode(), Op::Shr(0xA, 0xB));
+        assert_eq!(Instruction::new(0xF000).decode(), Op::LdILong);
+        assert_eq!(Instruction::new(0x00C4).decode(), Op::Scd(4));
+        assert_eq!(Instruction::new(0x5123).decode(), Op::LoadRange(1, 2));
+    }
+
+    #[test]
+    fn test_decode_unknown() {
+        assert_eq!(Instruction::new(0x5121).decode(), Op::Unknown(0x5121));
+        assert_eq!(Instruction::new(0xE1FF).decode(), Op::Unknown(0xE1FF));
+        assert_eq!(Instruction::new(0x9121).decode(), Op::Unknown(0x9121));
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        for opcode in 0..=0xFFFFu16 {
+            let op = Instruction::new(opcode).decode();
+            assert_eq!(op.encode(), opcode, "{:?}", op);
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Instruction::new(0xA2EA).decode().to_string(), "LD I, 0x2EA");
+        assert_eq!(Instruction::new(0xDAB6).decode().to_string(), "DRW VA, VB, 6");
     }
 }
\ No newline at end of file
@@ -1,5 +1,10 @@
 
 
+pub mod bus;
 pub mod chip8;
+pub mod error;
 pub mod instruction;
+pub mod quirks;
+pub mod rng;
+pub mod savestate;
 
@@ -0,0 +1,171 @@
+
+/// How FX55/FX65 leave `I` after storing or loading registers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexIncrement {
+    /// `I` is left untouched (SUPER-CHIP)
+    None,
+    /// `I` is incremented by X (CHIP-48)
+    X,
+    /// `I` is incremented by X + 1 (COSMAC VIP, XO-CHIP)
+    XPlusOne,
+}
+
+/// What DXYN does with sprites that reach past the edge of the screen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpriteEdge {
+    /// Pixels off screen are dropped, a sprite starting off screen isn't drawn
+    Clip,
+    /// The starting position wraps onto the screen, the rest of the sprite
+    /// is clipped (COSMAC VIP, CHIP-48, SUPER-CHIP)
+    WrapStart,
+    /// Every pixel wraps around to the opposite edge (XO-CHIP)
+    Wrap,
+}
+
+/// Interpretations of the opcodes that behave differently across Chip8
+/// implementations. Pick one of the presets to match the platform a ROM was
+/// written for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6/8XYE shift VY into VX instead of shifting VX in place
+    pub shift_vy: bool,
+    /// What FX55/FX65 do to `I`
+    pub load_store: IndexIncrement,
+    /// BXNN jumps to XNN + VX instead of NNN + V0
+    pub jump_vx: bool,
+    /// 8XY1/8XY2/8XY3 reset VF to 0
+    pub vf_reset: bool,
+    /// How DXYN treats the screen edges
+    pub sprite_edge: SpriteEdge,
+    /// DXYN in high resolution sets VF to the number of sprite rows that
+    /// collided or were clipped off the bottom, as SUPER-CHIP 1.1 did,
+    /// instead of 1
+    pub collision_rows: bool,
+    /// 64 KiB of addressable memory as on XO-CHIP instead of 4 KiB
+    pub large_memory: bool,
+    /// FX0A waits for the key to be released as well as pressed, as on the
+    /// COSMAC VIP, instead of taking the first key held
+    pub key_release: bool,
+    /// DXYN waits for the next 60 Hz frame before drawing, as the COSMAC VIP
+    /// waits for the vertical blank interrupt
+    pub display_wait: bool,
+    /// FX1E sets VF to 1 when I goes past the end of memory and 0 otherwise,
+    /// as the Amiga interpreter did
+    pub index_overflow: bool,
+}
+
+/// Names accepted by `Quirks::from_name`.
+pub const PRESETS: [&str; 5] = ["default", "vip", "chip48", "schip", "xochip"];
+
+impl Quirks {
+    pub const VIP: Quirks = Quirks {
+        shift_vy: true,
+        load_store: IndexIncrement::XPlusOne,
+        jump_vx: false,
+        vf_reset: true,
+        sprite_edge: SpriteEdge::WrapStart,
+        collision_rows: false,
+        large_memory: false,
+        key_release: true,
+        display_wait: true,
+        index_overflow: false,
+    };
+
+    pub const CHIP48: Quirks = Quirks {
+        shift_vy: false,
+        load_store: IndexIncrement::X,
+        jump_vx: true,
+        vf_reset: false,
+        sprite_edge: SpriteEdge::WrapStart,
+        collision_rows: false,
+        large_memory: false,
+        key_release: true,
+        display_wait: false,
+        index_overflow: false,
+    };
+
+    pub const SCHIP: Quirks = Quirks {
+        shift_vy: false,
+        load_store: IndexIncrement::None,
+        jump_vx: true,
+        vf_reset: false,
+        sprite_edge: SpriteEdge::WrapStart,
+        collision_rows: true,
+        large_memory: false,
+        key_release: true,
+        display_wait: false,
+        index_overflow: false,
+    };
+
+    pub const XOCHIP: Quirks = Quirks {
+        shift_vy: true,
+        load_store: IndexIncrement::XPlusOne,
+        jump_vx: false,
+        vf_reset: false,
+        sprite_edge: SpriteEdge::Wrap,
+        collision_rows: false,
+        large_memory: true,
+        key_release: true,
+        display_wait: false,
+        index_overflow: false,
+    };
+
+    pub fn from_name(name: &str) -> Option<Quirks> {
+        return match name.to_ascii_lowercase().as_str() {
+            "default" => Some(Quirks::default()),
+            "vip" => Some(Quirks::VIP),
+            "chip48" | "chip-48" => Some(Quirks::CHIP48),
+            "schip" | "superchip" => Some(Quirks::SCHIP),
+            "xochip" | "xo-chip" => Some(Quirks::XOCHIP),
+            _ => None,
+        };
+    }
+}
+
+impl Default for Quirks {
+    /// The behaviour this interpreter has always had: shifts ignore VY, I is
+    /// untouched by FX55/FX65, BNNN uses V0, VF is never reset, sprites clip
+    /// without wrapping where they start, memory is 4 KiB, FX0A doesn't wait
+    /// for the key to be released, DXYN draws straight away and sets VF to 1
+    /// on any collision, and FX1E leaves VF alone.
+    fn default() -> Self {
+        return Quirks {
+            shift_vy: false,
+            load_store: IndexIncrement::None,
+            jump_vx: false,
+            vf_reset: false,
+            sprite_edge: SpriteEdge::Clip,
+            collision_rows: false,
+            large_memory: false,
+            key_release: false,
+            display_wait: false,
+            index_overflow: false,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(Quirks::from_name("vip"), Some(Quirks::VIP));
+        assert_eq!(Quirks::from_name("CHIP-48"), Some(Quirks::CHIP48));
+        assert_eq!(Quirks::from_name("schip"), Some(Quirks::SCHIP));
+        assert_eq!(Quirks::from_name("xochip"), Some(Quirks::XOCHIP));
+        assert_eq!(Quirks::from_name("default"), Some(Quirks::default()));
+    }
+
+    #[test]
+    fn test_from_name_unknown() {
+        assert_eq!(Quirks::from_name("chip9"), None);
+    }
+
+    #[test]
+    fn test_presets_all_parse() {
+        for name in PRESETS.iter() {
+            assert!(Quirks::from_name(name).is_some());
+        }
+    }
+}
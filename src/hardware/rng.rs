@@ -0,0 +1,83 @@
+/// xorshift64* generator behind CXKK. It's owned by the machine and its
+/// state is a single word, so runs can be reproduced from a seed and the
+/// state can go in save states.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Any seed is fine, it's mixed first so nearby seeds give unrelated runs
+    pub fn new(seed: u64) -> Self {
+        // splitmix64 finalizer
+        let mut z = seed.wrapping_add(0x9e3779b97f4a7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+        return Rng { state: if z == 0 { 1 } else { z } };
+    }
+
+    /// Seeded from the operating system
+    pub fn from_entropy() -> Self {
+        return Rng::new(rand::random());
+    }
+
+    /// Resume from `state`, which must have come from `state()`
+    pub fn from_state(state: u64) -> Option<Self> {
+        if state == 0 {
+            return None;
+        }
+        return Some(Rng { state });
+    }
+
+    pub fn state(&self) -> u64 {
+        return self.state;
+    }
+
+    pub fn next_u8(&mut self) -> u8 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        return (self.state.wrapping_mul(0x2545f4914f6cdd1d) >> 56) as u8;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_sequence() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        for _ in 0..100 {
+            assert_eq!(a.next_u8(), b.next_u8());
+        }
+    }
+
+    #[test]
+    fn test_seeds_differ() {
+        let a: Vec<u8> = (0..16).scan(Rng::new(1), |r, _| Some(r.next_u8())).collect();
+        let b: Vec<u8> = (0..16).scan(Rng::new(2), |r, _| Some(r.next_u8())).collect();
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn test_resume_from_state() {
+        let mut a = Rng::new(7);
+        a.next_u8();
+        let mut b = Rng::from_state(a.state()).unwrap();
+        assert_eq!(a.next_u8(), b.next_u8());
+        assert_eq!(Rng::from_state(0), None);
+    }
+
+    #[test]
+    fn test_spread() {
+        let mut rng = Rng::new(0);
+        let mut seen = [false; 256];
+        for _ in 0..4096 {
+            seen[rng.next_u8() as usize] = true;
+        }
+        assert!(seen.iter().all(|s| *s));
+    }
+}
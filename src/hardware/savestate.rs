@@ -0,0 +1,241 @@
+use crate::hardware::bus::BusPolicy;
+use crate::hardware::error::EmuError;
+use crate::hardware::quirks::{IndexIncrement, Quirks, SpriteEdge};
+
+/// Leads every save state so other files are rejected straight away
+pub const MAGIC: &[u8; 4] = b"C8ST";
+
+/// Bumped whenever the layout changes, older states are rejected rather than
+/// misread
+pub const VERSION: u16 = 6;
+
+/// 64 bit FNV-1a hash identifying the ROM a state or recording belongs to
+pub fn rom_hash(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in data.iter() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    return hash;
+}
+
+/// Big endian encoder for the fields of a state
+pub(crate) struct Writer {
+    pub data: Vec<u8>,
+}
+
+impl Writer {
+    pub fn new() -> Self {
+        return Writer { data: Vec::new() };
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn bytes(&mut self, value: &[u8]) {
+        self.data.extend_from_slice(value);
+    }
+
+    /// Length prefixed bytes
+    pub fn block(&mut self, value: &[u8]) {
+        self.u32(value.len() as u32);
+        self.bytes(value);
+    }
+
+    pub fn quirks(&mut self, quirks: &Quirks) {
+        self.bool(quirks.shift_vy);
+        self.u8(match quirks.load_store {
+            IndexIncrement::None => 0,
+            IndexIncrement::X => 1,
+            IndexIncrement::XPlusOne => 2,
+        });
+        self.bool(quirks.jump_vx);
+        self.bool(quirks.vf_reset);
+        self.u8(match quirks.sprite_edge {
+            SpriteEdge::Clip => 0,
+            SpriteEdge::WrapStart => 1,
+            SpriteEdge::Wrap => 2,
+        });
+        self.bool(quirks.collision_rows);
+        self.bool(quirks.large_memory);
+        self.bool(quirks.key_release);
+        self.bool(quirks.display_wait);
+        self.bool(quirks.index_overflow);
+    }
+
+    pub fn bus_policy(&mut self, policy: BusPolicy) {
+        self.u8(match policy {
+            BusPolicy::Error => 0,
+            BusPolicy::Wrap => 1,
+            BusPolicy::Log => 2,
+        });
+    }
+}
+
+/// Decoder matching `Writer`, running out of data is a `BadState` error
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        return Reader { data };
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.data.is_empty();
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], EmuError> {
+        if self.data.len() < len {
+            return Err(EmuError::BadState);
+        }
+        let (head, rest) = self.data.split_at(len);
+        self.data = rest;
+        return Ok(head);
+    }
+
+    pub fn u8(&mut self) -> Result<u8, EmuError> {
+        return Ok(self.bytes(1)?[0]);
+    }
+
+    pub fn bool(&mut self) -> Result<bool, EmuError> {
+        return match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(EmuError::BadState),
+        };
+    }
+
+    pub fn u16(&mut self) -> Result<u16, EmuError> {
+        let bytes = self.bytes(2)?;
+        return Ok(u16::from_be_bytes([bytes[0], bytes[1]]));
+    }
+
+    pub fn u32(&mut self) -> Result<u32, EmuError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.bytes(4)?);
+        return Ok(u32::from_be_bytes(bytes));
+    }
+
+    pub fn u64(&mut self) -> Result<u64, EmuError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.bytes(8)?);
+        return Ok(u64::from_be_bytes(bytes));
+    }
+
+    pub fn block(&mut self) -> Result<&'a [u8], EmuError> {
+        let len = self.u32()? as usize;
+        return self.bytes(len);
+    }
+
+    /// Fill a fixed size array
+    pub fn array(&mut self, out: &mut [u8]) -> Result<(), EmuError> {
+        out.copy_from_slice(self.bytes(out.len())?);
+        return Ok(());
+    }
+
+    pub fn quirks(&mut self) -> Result<Quirks, EmuError> {
+        return Ok(Quirks {
+            shift_vy: self.bool()?,
+            load_store: match self.u8()? {
+                0 => IndexIncrement::None,
+                1 => IndexIncrement::X,
+                2 => IndexIncrement::XPlusOne,
+                _ => return Err(EmuError::BadState),
+            },
+            jump_vx: self.bool()?,
+            vf_reset: self.bool()?,
+            sprite_edge: match self.u8()? {
+                0 => SpriteEdge::Clip,
+                1 => SpriteEdge::WrapStart,
+                2 => SpriteEdge::Wrap,
+                _ => return Err(EmuError::BadState),
+            },
+            collision_rows: self.bool()?,
+            large_memory: self.bool()?,
+            key_release: self.bool()?,
+            display_wait: self.bool()?,
+            index_overflow: self.bool()?,
+        });
+    }
+
+    pub fn bus_policy(&mut self) -> Result<BusPolicy, EmuError> {
+        return match self.u8()? {
+            0 => Ok(BusPolicy::Error),
+            1 => Ok(BusPolicy::Wrap),
+            2 => Ok(BusPolicy::Log),
+            _ => Err(EmuError::BadState),
+        };
+    }
+}
+
+/// Check the magic, version and ROM hash of a state, returning the machine
+/// state that follows them
+pub(crate) fn read_header(data: &[u8], rom_hash: u64) -> Result<Reader<'_>, EmuError> {
+    let mut reader = Reader::new(data);
+    if reader.bytes(MAGIC.len())? != MAGIC {
+        return Err(EmuError::BadState);
+    }
+    let version = reader.u16()?;
+    if version != VERSION {
+        return Err(EmuError::StateVersion { found: version, expected: VERSION });
+    }
+    if reader.u64()? != rom_hash {
+        return Err(EmuError::StateRomMismatch);
+    }
+    return Ok(reader);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rom_hash() {
+        assert_eq!(rom_hash(&[]), 0xcbf29ce484222325);
+        assert_eq!(rom_hash(b"a"), 0xaf63dc4c8601ec8c);
+        assert_ne!(rom_hash(&[0x00, 0xE0]), rom_hash(&[0xE0, 0x00]));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut writer = Writer::new();
+        writer.u8(1);
+        writer.u16(0x0203);
+        writer.u64(4);
+        writer.block(&[5, 6]);
+        writer.quirks(&Quirks::SCHIP);
+
+        let mut reader = Reader::new(&writer.data);
+        assert_eq!(reader.u8().unwrap(), 1);
+        assert_eq!(reader.u16().unwrap(), 0x0203);
+        assert_eq!(reader.u64().unwrap(), 4);
+        assert_eq!(reader.block().unwrap(), &[5, 6]);
+        assert_eq!(reader.quirks().unwrap(), Quirks::SCHIP);
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut reader = Reader::new(&[0, 0, 0, 4, 1]);
+        assert!(matches!(reader.block(), Err(EmuError::BadState)));
+    }
+}
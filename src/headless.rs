@@ -0,0 +1,360 @@
+use crate::hardware::chip8::Chip8;
+use crate::hardware::error::EmuError;
+
+/// Frames run when no limit is given, ten seconds of emulated time
+pub const DEFAULT_FRAMES: u32 = 600;
+
+pub const DUMP_FORMATS: [&str; 4] = ["ascii", "png", "blocks", "braille"];
+
+// ASCII art for each plane combination, then grey levels matching the SDL
+// frontend's palette
+const ASCII: [char; 4] = ['.', '#', '+', '%'];
+const GREYS: [u8; 4] = [0, 255, 170, 85];
+
+/// When to stop a run without a frontend
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Options {
+    pub max_frames: u32,
+    /// Stop when the PC reaches this address
+    pub until_pc: Option<u16>,
+    /// Stop once the program settles into a jump to itself, or waits on
+    /// FX0A for a key that will never come
+    pub until_loop: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        return Options {
+            max_frames: DEFAULT_FRAMES,
+            until_pc: None,
+            until_loop: false,
+        };
+    }
+}
+
+/// Why a run stopped
+#[derive(Debug)]
+pub enum Outcome {
+    /// `max_frames` ran without meeting a stop condition
+    FramesElapsed,
+    ReachedPc(u16),
+    InfiniteLoop(u16),
+    WaitingForKey(u16),
+    /// The program ran SUPER-CHIP's 00FD
+    Exited,
+    Fault(EmuError),
+}
+
+impl Outcome {
+    /// Process exit status for scripts: 0 for a clean stop, 1 for a fault and
+    /// 2 when a requested stop condition was never met
+    pub fn exit_code(&self, options: &Options) -> i32 {
+        return match self {
+            Outcome::Fault(_) => 1,
+            Outcome::FramesElapsed if options.until_pc.is_some() || options.until_loop => 2,
+            _ => 0,
+        };
+    }
+}
+
+/// Run `inter` with no input until one of the stop conditions in `options`,
+/// returning how it stopped and how many frames ran
+pub fn run(inter: &mut Chip8, options: &Options) -> (Outcome, u32) {
+    for frame in 0..options.max_frames {
+        for _ in 0..inter.cycles_per_frame() {
+            if let Some(outcome) = check_stop(inter, options) {
+                return (outcome, frame);
+            }
+            if let Err(e) = inter.run_cycle() {
+                return (Outcome::Fault(e), frame);
+            }
+        }
+        inter.tick_timers();
+    }
+    return (check_stop(inter, options).unwrap_or(Outcome::FramesElapsed), options.max_frames);
+}
+
+fn check_stop(inter: &Chip8, options: &Options) -> Option<Outcome> {
+    let pc = inter.pc();
+    if inter.exited() {
+        return Some(Outcome::Exited);
+    }
+    if options.until_pc == Some(pc) {
+        return Some(Outcome::ReachedPc(pc));
+    }
+    if options.until_loop {
+        match inter.current_opcode() {
+            // JP only reaches the first 4 KiB, so higher up it jumps elsewhere
+            Some(op) if op & 0xF000 == 0x1000 && pc <= 0xFFF && op & 0x0FFF == pc => {
+                return Some(Outcome::InfiniteLoop(pc));
+            },
+            Some(op) if op & 0xF0FF == 0xF00A && inter.keys().iter().all(|k| *k == 0) => {
+                return Some(Outcome::WaitingForKey(pc));
+            },
+            _ => {},
+        }
+    }
+    return None;
+}
+
+/// The screen as one line of text per row
+pub fn ascii(inter: &Chip8) -> String {
+    let mut art = String::new();
+    for row in inter.screen.chunks(inter.width()) {
+        art.extend(row.iter().map(|pixel| ASCII[(pixel & 0x3) as usize]));
+        art.push('\n');
+    }
+    return art;
+}
+
+/// The screen in Unicode half blocks, each character covering two pixels
+/// stacked vertically
+pub fn half_blocks(inter: &Chip8) -> String {
+    let width = inter.width();
+    let lit = |x: usize, y: usize| inter.screen[y * width + x] != 0;
+
+    let mut art = String::new();
+    for y in (0..inter.height()).step_by(2) {
+        for x in 0..width {
+            art.push(match (lit(x, y), lit(x, y + 1)) {
+                (false, false) => ' ',
+                (true, false) => '\u{2580}',
+                (false, true) => '\u{2584}',
+                (true, true) => '\u{2588}',
+            });
+        }
+        art.push('\n');
+    }
+    return art;
+}
+
+/// The screen in Unicode braille, each character covering a 2x4 block of
+/// pixels
+pub fn braille(inter: &Chip8) -> String {
+    // Dot bits in the braille block, indexed by [row][column]
+    const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+    let width = inter.width();
+
+    let mut art = String::new();
+    for y in (0..inter.height()).step_by(4) {
+        for x in (0..width).step_by(2) {
+            let mut dots = 0;
+            for (row, bits) in DOTS.iter().enumerate() {
+                for (column, bit) in bits.iter().enumerate() {
+                    if inter.screen[(y + row) * width + x + column] != 0 {
+                        dots |= bit;
+                    }
+                }
+            }
+            art.push(std::char::from_u32(0x2800 + dots).unwrap());
+        }
+        art.push('\n');
+    }
+    return art;
+}
+
+/// The screen as a greyscale PNG, one image pixel per screen pixel
+pub fn png(inter: &Chip8) -> Vec<u8> {
+    let (width, height) = (inter.width(), inter.height());
+
+    // Each scanline starts with filter type 0
+    let mut raw = Vec::with_capacity((width + 1) * height);
+    for row in inter.screen.chunks(width) {
+        raw.push(0);
+        raw.extend(row.iter().map(|pixel| GREYS[(pixel & 0x3) as usize]));
+    }
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bit greyscale, deflate, no filtering, not interlaced
+    header.extend_from_slice(&[8, 0, 0, 0, 0]);
+
+    let mut png = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+    png_chunk(&mut png, b"IHDR", &header);
+    png_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    png_chunk(&mut png, b"IEND", &[]);
+    return png;
+}
+
+fn png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Wrap data in a zlib stream of uncompressed deflate blocks, the screen is
+/// small enough that compressing isn't worth the code
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(blocks.peek().is_none() as u8);
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data.iter() {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    out.extend_from_slice(&(b << 16 | a).to_be_bytes());
+    return out;
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFFFFFFu32;
+    for byte in data.iter() {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { 0xEDB88320 ^ (crc >> 1) } else { crc >> 1 };
+        }
+    }
+    return !crc;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hardware::quirks::Quirks;
+
+    fn machine(rom: &[u8]) -> Chip8 {
+        let mut inter = Chip8::new(Quirks::VIP);
+        inter.load_rom(rom).unwrap();
+        return inter;
+    }
+
+    #[test]
+    fn test_frames_elapsed() {
+        let mut inter = machine(&[0x70, 0x01, 0x12, 0x00]);
+        let options = Options { max_frames: 3, ..Options::default() };
+        let (outcome, frames) = run(&mut inter, &options);
+        assert!(matches!(outcome, Outcome::FramesElapsed));
+        assert_eq!(frames, 3);
+        assert_eq!(outcome.exit_code(&options), 0);
+    }
+
+    #[test]
+    fn test_infinite_loop() {
+        let mut inter = machine(&[0x60, 0x05, 0x12, 0x02]);
+        let options = Options { until_loop: true, ..Options::default() };
+        let (outcome, frames) = run(&mut inter, &options);
+        assert!(matches!(outcome, Outcome::InfiniteLoop(0x202)));
+        assert_eq!(frames, 0);
+        assert_eq!(inter.registers()[0], 5);
+        assert_eq!(outcome.exit_code(&options), 0);
+    }
+
+    #[test]
+    fn test_jump_above_4k_is_not_a_loop() {
+        let mut inter = Chip8::new(Quirks::XOCHIP);
+        // Straight through to 0x1202, which jumps back to 0x202
+        let mut rom = [0x60, 0x00].repeat(0x801);
+        rom.extend_from_slice(&[0x12, 0x02]);
+        inter.load_rom(&rom).unwrap();
+        inter.set_cycles_per_frame(1000);
+        let options = Options { max_frames: 5, until_loop: true, ..Options::default() };
+        assert!(matches!(run(&mut inter, &options).0, Outcome::FramesElapsed));
+    }
+
+    #[test]
+    fn test_waiting_for_key() {
+        let mut inter = machine(&[0xF1, 0x0A]);
+        let options = Options { until_loop: true, ..Options::default() };
+        assert!(matches!(run(&mut inter, &options).0, Outcome::WaitingForKey(0x200)));
+    }
+
+    #[test]
+    fn test_until_pc() {
+        let mut inter = machine(&[0x00, 0xE0, 0x00, 0xE0, 0x00, 0xE0]);
+        let options = Options { until_pc: Some(0x204), ..Options::default() };
+        assert!(matches!(run(&mut inter, &options).0, Outcome::ReachedPc(0x204)));
+    }
+
+    #[test]
+    fn test_condition_not_met() {
+        let mut inter = machine(&[0x12, 0x02, 0x12, 0x00]);
+        let options = Options { max_frames: 2, until_pc: Some(0x300), until_loop: false };
+        let (outcome, _) = run(&mut inter, &options);
+        assert!(matches!(outcome, Outcome::FramesElapsed));
+        assert_eq!(outcome.exit_code(&options), 2);
+    }
+
+    #[test]
+    fn test_fault() {
+        let mut inter = machine(&[0x00, 0xEE]);
+        let (outcome, _) = run(&mut inter, &Options::default());
+        assert!(matches!(outcome, Outcome::Fault(EmuError::StackUnderflow)));
+        assert_eq!(outcome.exit_code(&Options::default()), 1);
+    }
+
+    #[test]
+    fn test_exited() {
+        let mut inter = machine(&[0x00, 0xFD]);
+        assert!(matches!(run(&mut inter, &Options::default()).0, Outcome::Exited));
+    }
+
+    #[test]
+    fn test_ascii() {
+        let mut inter = machine(&[0xA2, 0x06, 0xD0, 0x01, 0x12, 0x04, 0xA0, 0x00]);
+        let options = Options { until_loop: true, ..Options::default() };
+        run(&mut inter, &options);
+        let art = ascii(&inter);
+        let lines: Vec<&str> = art.lines().collect();
+        assert_eq!(lines.len(), 32);
+        assert_eq!(&lines[0][..4], "#.#.");
+        assert!(lines[1].chars().all(|c| c == '.'));
+    }
+
+    #[test]
+    fn test_half_blocks() {
+        let mut inter = machine(&[]);
+        inter.screen[0] = 1;
+        inter.screen[64 + 1] = 1;
+        inter.screen[2] = 1;
+        inter.screen[64 + 2] = 2;
+        let art = half_blocks(&inter);
+        let lines: Vec<&str> = art.lines().collect();
+        assert_eq!(lines.len(), 16);
+        assert!(lines[0].starts_with("\u{2580}\u{2584}\u{2588} "));
+        assert_eq!(lines[0].chars().count(), 64);
+    }
+
+    #[test]
+    fn test_braille() {
+        let mut inter = machine(&[]);
+        inter.screen[0] = 1;
+        inter.screen[3 * 64 + 1] = 1;
+        let art = braille(&inter);
+        let lines: Vec<&str> = art.lines().collect();
+        assert_eq!(lines.len(), 8);
+        assert_eq!(lines[0].chars().count(), 32);
+        assert_eq!(lines[0].chars().next(), Some('\u{2881}'));
+        assert_eq!(lines[0].chars().nth(1), Some('\u{2800}'));
+    }
+
+    #[test]
+    fn test_png() {
+        let inter = machine(&[]);
+        let png = png(&inter);
+        assert_eq!(&png[..8], &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]);
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..24], &[0, 0, 0, 64, 0, 0, 0, 32]);
+        assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+    }
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(crc32(b"IEND"), 0xAE426082);
+        assert_eq!(&zlib_stored(b"a")[8..], &[0x00, 0x62, 0x00, 0x62]);
+    }
+}
@@ -0,0 +1,131 @@
+use sdl2;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use std::f32::consts::PI;
+use chip8::Chip8;
+
+const SAMPLE_RATE: i32 = 44100;
+const PATTERN_BITS: f32 = 128.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+    Sawtooth,
+}
+
+impl Waveform {
+    pub fn from_name(name: &str) -> Option<Waveform> {
+        return match name {
+            "square" => Some(Waveform::Square),
+            "sine" => Some(Waveform::Sine),
+            "triangle" => Some(Waveform::Triangle),
+            "sawtooth" => Some(Waveform::Sawtooth),
+            _ => None,
+        };
+    }
+
+    /// Sample for a phase in [0, 1), in the range [-1, 1]
+    fn sample(&self, phase: f32) -> f32 {
+        return match self {
+            Waveform::Square => if phase < 0.5 { 1.0 } else { -1.0 },
+            Waveform::Sine => (phase * 2.0 * PI).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+        };
+    }
+}
+
+pub struct ToneSettings {
+    pub frequency: f32,
+    /// 0.0 to 1.0
+    pub volume: f32,
+    pub waveform: Waveform,
+}
+
+struct Tone {
+    settings: ToneSettings,
+    phase: f32,
+    // What the device gave us, which may not be SAMPLE_RATE
+    sample_rate: f32,
+    // XO-CHIP pattern and the rate its bits are played at
+    pattern: Option<([u8; 16], f32)>,
+}
+
+impl AudioCallback for Tone {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = match self.pattern {
+                Some((pattern, rate)) => {
+                    let bit = self.phase as usize;
+                    self.phase = (self.phase + rate / self.sample_rate) % PATTERN_BITS;
+                    if pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 { 1.0 } else { -1.0 }
+                },
+                None => {
+                    let value = self.settings.waveform.sample(self.phase);
+                    self.phase = (self.phase + self.settings.frequency / self.sample_rate) % 1.0;
+                    value
+                },
+            } * self.settings.volume;
+        }
+    }
+}
+
+/// Plays a tone whenever the sound timer is running
+pub struct Beeper {
+    device: AudioDevice<Tone>,
+    playing: bool,
+}
+
+impl Beeper {
+    pub fn new(sdl_ctx: &sdl2::Sdl, settings: ToneSettings) -> Result<Self, String> {
+        let audio = sdl_ctx.audio()?;
+        let desired = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE),
+            channels: Some(1),
+            samples: None,
+        };
+
+        let device = audio.open_playback(None, &desired, |spec| Tone {
+            settings,
+            phase: 0.0,
+            sample_rate: spec.freq as f32,
+            pattern: None,
+        })?;
+
+        return Ok(Beeper { device, playing: false });
+    }
+
+    /// Start or stop the tone to match the sound timer, call once per frame
+    pub fn update(&mut self, inter: &Chip8) {
+        if inter.uses_audio_pattern() {
+            let mut tone = self.device.lock();
+            let pattern = Some((*inter.audio_pattern(), inter.pattern_rate()));
+            if tone.pattern != pattern {
+                // Phase is measured in bits for patterns but cycles for tones
+                if tone.pattern.is_none() {
+                    tone.phase = 0.0;
+                }
+                tone.pattern = pattern;
+            }
+        } else {
+            // Back to the plain tone, e.g. after rewinding or loading a state
+            let mut tone = self.device.lock();
+            if tone.pattern.is_some() {
+                tone.pattern = None;
+                tone.phase = 0.0;
+            }
+        }
+
+        if inter.sound_active() != self.playing {
+            self.playing = inter.sound_active();
+            if self.playing {
+                self.device.resume();
+            } else {
+                self.device.pause();
+            }
+        }
+    }
+}
@@ -0,0 +1,349 @@
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+use chip8::{Chip8, EmuError, Instruction};
+
+const HELP: &str = "\
+s, step [n]         run n instructions (default 1)
+n, next             step over calls
+c, continue         run until a breakpoint or watchpoint
+b, break <addr>     stop when PC reaches addr
+d, delete <addr>    remove a breakpoint
+w, watch <addr|vX>  stop when memory at addr is written or register vX changes
+u, unwatch <addr|vX>
+l, list             list breakpoints and watchpoints
+r, regs             print registers, I, PC, stack and timers
+m, mem <addr> [len] print memory
+q, quit             exit the emulator
+Addresses are hex. An empty line repeats the last command.";
+
+enum Mode {
+    Running,
+    // Pause after this many more instructions
+    Stepping(u32),
+    // Pause once a call returns to this address at the same stack depth
+    StepOver { return_to: u16, depth: usize },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Watch {
+    Memory(u16),
+    Register(usize),
+}
+
+/// Command line debugger that runs the program one instruction at a time
+pub struct Debugger {
+    mode: Mode,
+    // PC of the last instruction run, so a breakpoint on an instruction that
+    // waits (FX0A, or DXYN for the display) stops once rather than every cycle
+    previous_pc: Option<u16>,
+    breakpoints: BTreeSet<u16>,
+    watches: BTreeSet<Watch>,
+    last_command: String,
+}
+
+impl Debugger {
+    /// Starts paused at the first instruction
+    pub fn new() -> Self {
+        return Debugger {
+            mode: Mode::Stepping(0),
+            previous_pc: None,
+            breakpoints: BTreeSet::new(),
+            watches: BTreeSet::new(),
+            last_command: String::from("step"),
+        };
+    }
+
+    /// Run one frame's worth of instructions followed by a timer tick,
+    /// dropping to the prompt when a breakpoint or watchpoint is hit.
+    /// `redraw` is called before prompting so the screen is up to date.
+    /// Returns false if the user asked to quit.
+    pub fn run_frame(&mut self, inter: &mut Chip8, redraw: &mut dyn FnMut(&Chip8)) -> Result<bool, EmuError> {
+        for _ in 0..inter.cycles_per_frame() {
+            if inter.exited() {
+                break;
+            }
+
+            let pc = inter.pc();
+            if self.should_stop(pc, inter.stack().len()) {
+                if self.at_breakpoint(pc) {
+                    println!("Breakpoint at {:04X}", pc);
+                }
+                redraw(inter);
+                if !self.prompt(inter) {
+                    return Ok(false);
+                }
+            }
+
+            let before = self.watched_values(inter);
+            self.previous_pc = Some(pc);
+            inter.run_cycle()?;
+            self.check_watches(inter, &before);
+
+            if let Mode::Stepping(n) = self.mode {
+                self.mode = Mode::Stepping(n.saturating_sub(1));
+            }
+        }
+        inter.tick_timers();
+        return Ok(true);
+    }
+
+    /// Whether PC has just arrived at a breakpoint, staying on one doesn't count
+    fn at_breakpoint(&self, pc: u16) -> bool {
+        return self.breakpoints.contains(&pc) && self.previous_pc != Some(pc);
+    }
+
+    /// Whether to prompt before running the instruction at `pc`, `depth`
+    /// being the number of calls on the stack
+    fn should_stop(&self, pc: u16, depth: usize) -> bool {
+        if self.at_breakpoint(pc) {
+            return true;
+        }
+        return match self.mode {
+            Mode::Running => false,
+            Mode::Stepping(n) => n == 0,
+            Mode::StepOver { return_to, depth: return_depth } => {
+                pc == return_to && depth == return_depth
+            },
+        };
+    }
+
+    fn watched_values(&self, inter: &Chip8) -> Vec<u8> {
+        return self.watches.iter().map(|w| read_watch(inter, *w)).collect();
+    }
+
+    fn check_watches(&mut self, inter: &Chip8, before: &[u8]) {
+        for (watch, old) in self.watches.iter().zip(before.iter()) {
+            let new = read_watch(inter, *watch);
+            if triggered(*watch, *old, new, inter.written()) {
+                let action = match watch {
+                    Watch::Memory(_) => "written",
+                    Watch::Register(_) => "changed",
+                };
+                println!("{} {} {:02X} -> {:02X}", describe_watch(*watch), action, old, new);
+                self.mode = Mode::Stepping(0);
+            }
+        }
+    }
+
+    /// Read commands until one resumes execution, false means quit
+    fn prompt(&mut self, inter: &Chip8) -> bool {
+        print_current(inter);
+        let stdin = io::stdin();
+        loop {
+            print!("(chip8) ");
+            let _ = io::stdout().flush();
+
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+                return false;
+            }
+            let mut line = line.trim().to_string();
+            if line.is_empty() {
+                line = self.last_command.clone();
+            }
+            self.last_command = line.clone();
+
+            let args: Vec<&str> = line.split_whitespace().collect();
+            let result = match args[0] {
+                "s" | "step" => {
+                    match args.get(1).map(|n| n.parse::<u32>()) {
+                        None => self.mode = Mode::Stepping(1),
+                        Some(Ok(n)) if n > 0 => self.mode = Mode::Stepping(n),
+                        _ => {
+                            println!("step count must be a positive number");
+                            continue;
+                        },
+                    }
+                    return true;
+                },
+                "n" | "next" => {
+                    self.mode = match inter.current_opcode() {
+                        Some(op) if op & 0xF000 == 0x2000 => Mode::StepOver {
+                            return_to: inter.pc().wrapping_add(chip8::OPCODE_LEN),
+                            depth: inter.stack().len(),
+                        },
+                        _ => Mode::Stepping(1),
+                    };
+                    return true;
+                },
+                "c" | "continue" => {
+                    self.mode = Mode::Running;
+                    return true;
+                },
+                "q" | "quit" => return false,
+                "b" | "break" => parse_addr(args.get(1)).map(|addr| {
+                    self.breakpoints.insert(addr);
+                }),
+                "d" | "delete" => parse_addr(args.get(1)).map(|addr| {
+                    if !self.breakpoints.remove(&addr) {
+                        println!("No breakpoint at {:04X}", addr);
+                    }
+                }),
+                "w" | "watch" => parse_watch(args.get(1)).map(|watch| {
+                    self.watches.insert(watch);
+                }),
+                "u" | "unwatch" => parse_watch(args.get(1)).map(|watch| {
+                    if !self.watches.remove(&watch) {
+                        println!("Not watching {}", describe_watch(watch));
+                    }
+                }),
+                "l" | "list" => {
+                    for addr in self.breakpoints.iter() {
+                        println!("break {:04X}", addr);
+                    }
+                    for watch in self.watches.iter() {
+                        println!("watch {}", describe_watch(*watch));
+                    }
+                    Ok(())
+                },
+                "r" | "regs" => {
+                    print_registers(inter);
+                    Ok(())
+                },
+                "m" | "mem" => parse_addr(args.get(1)).and_then(|addr| {
+                    let len = match args.get(2) {
+                        Some(len) => parse_number(len)?,
+                        None => 16,
+                    };
+                    print_memory(inter, addr as usize, len);
+                    Ok(())
+                }),
+                "h" | "help" => {
+                    println!("{}", HELP);
+                    Ok(())
+                },
+                other => Err(format!("Unknown command {}, try help", other)),
+            };
+
+            if let Err(e) = result {
+                println!("{}", e);
+            }
+        }
+    }
+}
+
+fn read_watch(inter: &Chip8, watch: Watch) -> u8 {
+    return match watch {
+        Watch::Memory(addr) => inter.memory().get(addr as usize).copied().unwrap_or(0),
+        Watch::Register(reg) => inter.registers()[reg],
+    };
+}
+
+/// Memory watches stop on any store, even of the same value, registers only
+/// when they change
+fn triggered(watch: Watch, old: u8, new: u8, written: &[usize]) -> bool {
+    return match watch {
+        Watch::Memory(addr) => written.contains(&(addr as usize)),
+        Watch::Register(_) => old != new,
+    };
+}
+
+fn describe_watch(watch: Watch) -> String {
+    return match watch {
+        Watch::Memory(addr) => format!("[{:04X}]", addr),
+        Watch::Register(reg) => format!("V{:X}", reg),
+    };
+}
+
+fn parse_number(arg: &str) -> Result<usize, String> {
+    let digits = arg.trim_start_matches("0x").trim_start_matches("0X");
+    return usize::from_str_radix(digits, 16).map_err(|_| format!("{} isn't a hex number", arg));
+}
+
+fn parse_addr(arg: Option<&&str>) -> Result<u16, String> {
+    let arg = arg.ok_or("Expected an address")?;
+    let addr = parse_number(arg)?;
+    if addr > 0xFFFF {
+        return Err(format!("{} is out of range", arg));
+    }
+    return Ok(addr as u16);
+}
+
+fn parse_watch(arg: Option<&&str>) -> Result<Watch, String> {
+    let arg = arg.ok_or("Expected an address or register")?;
+    if arg.len() == 2 && (arg.starts_with('v') || arg.starts_with('V')) {
+        if let Ok(reg) = usize::from_str_radix(&arg[1..], 16) {
+            return Ok(Watch::Register(reg));
+        }
+    }
+    return parse_addr(Some(arg)).map(Watch::Memory);
+}
+
+fn print_current(inter: &Chip8) {
+    match inter.current_opcode() {
+        Some(op) => println!("{:04X}: {:04X}  {}", inter.pc(), op, Instruction::new(op).decode()),
+        None => println!("{:04X}: out of bounds", inter.pc()),
+    }
+}
+
+fn print_registers(inter: &Chip8) {
+    println!("{}", inter.register_dump());
+    let stack: Vec<String> = inter.stack().iter().map(|addr| format!("{:04X}", addr)).collect();
+    println!("Stack [{}]", stack.join(" "));
+}
+
+fn print_memory(inter: &Chip8, addr: usize, len: usize) {
+    let memory = inter.memory();
+    let end = addr.saturating_add(len).min(memory.len());
+    for start in (addr..end).step_by(16) {
+        let row: Vec<String> = memory[start..(start + 16).min(end)].iter()
+            .map(|b| format!("{:02X}", b))
+            .collect();
+        println!("{:04X}: {}", start, row.join(" "));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_addr() {
+        assert_eq!(parse_addr(Some(&"200")), Ok(0x200));
+        assert_eq!(parse_addr(Some(&"0xFFFF")), Ok(0xFFFF));
+        assert!(parse_addr(Some(&"10000")).is_err());
+        assert!(parse_addr(Some(&"zz")).is_err());
+        assert!(parse_addr(None).is_err());
+    }
+
+    #[test]
+    fn test_parse_watch() {
+        assert_eq!(parse_watch(Some(&"vA")), Ok(Watch::Register(0xA)));
+        assert_eq!(parse_watch(Some(&"V0")), Ok(Watch::Register(0)));
+        assert_eq!(parse_watch(Some(&"300")), Ok(Watch::Memory(0x300)));
+        assert!(parse_watch(Some(&"vz")).is_err());
+        assert!(parse_watch(None).is_err());
+    }
+
+    #[test]
+    fn test_breakpoint_stops_on_arrival_only() {
+        let mut debugger = Debugger::new();
+        debugger.mode = Mode::Running;
+        debugger.breakpoints.insert(0x204);
+        assert!(!debugger.should_stop(0x202, 0));
+        debugger.previous_pc = Some(0x202);
+        assert!(debugger.should_stop(0x204, 0));
+
+        // An FX0A at the breakpoint still waiting for a key
+        debugger.previous_pc = Some(0x204);
+        assert!(!debugger.should_stop(0x204, 0));
+    }
+
+    #[test]
+    fn test_step_over_waits_for_return() {
+        let mut debugger = Debugger::new();
+        debugger.mode = Mode::StepOver { return_to: 0x202, depth: 0 };
+        assert!(!debugger.should_stop(0x300, 1));
+        // Recursion back to the same address deeper in the stack
+        assert!(!debugger.should_stop(0x202, 1));
+        assert!(debugger.should_stop(0x202, 0));
+    }
+
+    #[test]
+    fn test_triggered() {
+        assert!(triggered(Watch::Memory(0x300), 5, 5, &[0x300]));
+        assert!(!triggered(Watch::Memory(0x300), 5, 5, &[0x301]));
+        assert!(triggered(Watch::Register(1), 5, 6, &[]));
+        assert!(!triggered(Watch::Register(1), 5, 5, &[0x300]));
+    }
+}
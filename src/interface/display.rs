@@ -1,56 +1,137 @@
 use sdl2;
-use sdl2::{pixels, rect::Rect, render::Canvas, video::Window};
-use crate::hardware::chip8::PIXEL_COUNT;
-use crate::UI_SCALE;
+use sdl2::pixels::{self, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture, TextureCreator};
+use sdl2::video::{FullscreenType, Window, WindowContext};
+use chip8::filter::Frame;
+use chip8::palette::{Palette, Rgb};
 
 pub struct Display {
-    canvas: Canvas<Window>
+    canvas: Canvas<Window>,
+    creator: TextureCreator<WindowContext>,
+    // The last frame, at whatever resolution it was drawn in. Textures
+    // aren't freed on drop with unsafe_textures, only with the canvas or
+    // when destroyed
+    texture: Option<(Texture, u32, u32)>,
+    colours: [Rgb; 4],
+    // Only scale by whole numbers, so every Chip8 pixel is the same size
+    integer: bool,
 }
 
 impl Display {
-    pub fn new(sdl_ctx: &sdl2::Sdl, title: &str, width: u32, height: u32,) -> Self {
+    /// A resizable window `scale` times the size of the low resolution
+    /// screen. `scaling` is "integer" or "aspect". Presenting waits for
+    /// vsync.
+    pub fn new(sdl_ctx: &sdl2::Sdl, title: &str, scale: u32, palette: &Palette, scaling: &str) -> Self {
         let video = sdl_ctx.video().unwrap();
         let window = video
             .window(
-                "chip8-rs",
-                width,
-                height
+                title,
+                chip8::WIDTH * scale,
+                chip8::HEIGHT * scale
             )
             .position_centered()
+            .resizable()
             .opengl()
             .build()
             .unwrap();
 
-        let mut canvas = window.into_canvas().build().unwrap();
+        let mut canvas = window.into_canvas().present_vsync().build().unwrap();
+        let creator = canvas.texture_creator();
 
-        canvas.set_draw_color(pixels::Color::RGB(0, 0, 0));
+        let bg = palette.colours[0];
+        canvas.set_draw_color(pixels::Color::RGB(bg[0], bg[1], bg[2]));
         canvas.clear();
         canvas.present();
 
-        return Display { canvas };
+        return Display {
+            canvas,
+            creator,
+            texture: None,
+            colours: palette.colours,
+            integer: scaling == "integer",
+        };
     }
 
-    pub fn draw_frame(&mut self, bitmap: &[u8; PIXEL_COUNT]) {
-        for y in 0..32 {
-            for x in 0..64 {
-                self.canvas.set_draw_color(
-                  match bitmap[self.point_to_index(x, y)] {
-                      0 => pixels::Color::BLACK,
-                      _ => pixels::Color::WHITE
-                  }
-                );
-                let _ = self.canvas.fill_rect(Rect::new(
-                    (x * UI_SCALE as usize) as i32,
-                    (y * UI_SCALE as usize) as i32,
-                    UI_SCALE as u32,
-                    UI_SCALE as u32
-                ));
+    /// Switch between a window and desktop fullscreen
+    pub fn toggle_fullscreen(&mut self) {
+        let window = self.canvas.window_mut();
+        let state = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+        if let Err(e) = window.set_fullscreen(state) {
+            eprintln!("Couldn't change fullscreen: {}", e);
+        }
+    }
+
+    /// Upload a frame to be shown by the next `present`. Any resolution
+    /// works, the texture follows it.
+    pub fn update(&mut self, frame: &Frame) {
+        let (width, height) = (frame.width as u32, frame.height as u32);
+        let stale = match &self.texture {
+            Some((_, w, h)) => (*w, *h) != (width, height),
+            None => true,
+        };
+        if stale {
+            let texture = self.creator
+                .create_texture_streaming(PixelFormatEnum::RGB24, width, height)
+                .unwrap();
+            if let Some((old, _, _)) = self.texture.replace((texture, width, height)) {
+                // The canvas that made it is still alive
+                unsafe { old.destroy() };
             }
         }
+
+        let colours = self.colours;
+        let (texture, _, _) = self.texture.as_mut().unwrap();
+        let _ = texture.with_lock(None, |pixels, pitch| {
+            for (i, (pixel, brightness)) in frame.pixels.iter().zip(frame.brightness.iter()).enumerate() {
+                let offset = i / frame.width * pitch + i % frame.width * 3;
+                let colour = colours[(pixel & 0x3) as usize];
+                // Dim pixels are mixed with the background
+                for c in 0..3 {
+                    let (fg, bg) = (colour[c] as u32, colours[0][c] as u32);
+                    pixels[offset + c] = ((fg * *brightness as u32 + bg * (255 - *brightness as u32)) / 255) as u8;
+                }
+            }
+        });
+    }
+
+    /// Show the last uploaded frame as large as fits the window while keeping
+    /// its shape, centred on the background colour. Blocks until vsync.
+    pub fn present(&mut self) {
+        let bg = self.colours[0];
+        self.canvas.set_draw_color(pixels::Color::RGB(bg[0], bg[1], bg[2]));
+        self.canvas.clear();
+        if let Some((texture, width, height)) = &self.texture {
+            let area = self.frame_area(*width, *height);
+            let _ = self.canvas.copy(texture, None, area);
+        }
         self.canvas.present();
     }
 
-    fn point_to_index(&self, x: usize, y: usize) -> usize {
-        return y * 64 + x;
+    /// Upload and present a bitmap straight away
+    pub fn draw_frame(&mut self, bitmap: &[u8], width: usize, height: usize) {
+        self.update(&Frame::new(bitmap, width, height));
+        self.present();
     }
-}
\ No newline at end of file
+
+    /// Where a `width` x `height` frame goes in the window
+    fn frame_area(&self, width: u32, height: u32) -> Rect {
+        let (window_width, window_height) = self.canvas.output_size().unwrap_or((width, height));
+        let (w, h) = if self.integer {
+            let scale = (window_width / width).min(window_height / height).max(1);
+            (width * scale, height * scale)
+        } else {
+            let scale = (window_width as f32 / width as f32).min(window_height as f32 / height as f32);
+            ((width as f32 * scale) as u32, (height as f32 * scale) as u32)
+        };
+        return Rect::new(
+            (window_width as i32 - w as i32) / 2,
+            (window_height as i32 - h as i32) / 2,
+            w.max(1),
+            h.max(1)
+        );
+    }
+}
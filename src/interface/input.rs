@@ -1,51 +1,136 @@
-use sdl2::event::Event;
+use sdl2::event::{Event, WindowEvent};
 use sdl2::EventPump;
 use sdl2::Sdl;
-use sdl2::keyboard::{Keycode, KeyboardState, Scancode};
+use sdl2::keyboard::{Keycode, KeyboardState, Mod, Scancode};
+use chip8::keymap::Keymap;
+use chip8::KeyEvent;
 
+const SLOT_KEYS: [Keycode; 9] = [
+    Keycode::F1, Keycode::F2, Keycode::F3, Keycode::F4, Keycode::F5,
+    Keycode::F6, Keycode::F7, Keycode::F8, Keycode::F9,
+];
+
+/// Host keys the frontend uses itself, which can't drive the keypad
+const RESERVED: [Scancode; 14] = [
+    Scancode::F1, Scancode::F2, Scancode::F3, Scancode::F4, Scancode::F5,
+    Scancode::F6, Scancode::F7, Scancode::F8, Scancode::F9,
+    Scancode::F10, Scancode::F11, Scancode::F12, Scancode::Backspace, Scancode::Escape,
+];
 
 pub struct Input {
     event_pump: EventPump,
+    scancodes: [Scancode; 16],
 }
 
 pub enum Command {
     Continue,
     Quit,
+    /// Shift+F1 to Shift+F9 save to slots 1 to 9
+    SaveState(u8),
+    /// F1 to F9 load from slots 1 to 9
+    LoadState(u8),
+    /// F12 opens the key rebinding screen
+    Rebind,
+    /// F10 moves on to the next display filter
+    NextFilter,
+    /// F11 switches fullscreen on and off
+    ToggleFullscreen,
+    /// The window was resized or uncovered and needs drawing again
+    Redraw,
+}
+
+/// What `read_key` saw while rebinding
+pub enum KeyPress {
+    Nothing,
+    Quit,
+    /// Esc
+    Cancel,
+    /// The SDL name of the key
+    Key(String),
 }
 
 impl Input {
-    pub fn new(context: &Sdl) -> Self {
-        return Input {
-            event_pump: context.event_pump().unwrap(),
-        }
+    pub fn new(context: &Sdl, keymap: &Keymap) -> Result<Self, String> {
+        let mut input = Input {
+            event_pump: context.event_pump()?,
+            scancodes: [Scancode::Escape; 16],
+        };
+        input.set_keymap(keymap)?;
+        return Ok(input);
     }
 
-    pub fn poll(&mut self, keys: &mut [u8; 16]) -> Command {
+    /// Fails if a key name isn't one SDL knows or is used by the frontend
+    pub fn set_keymap(&mut self, keymap: &Keymap) -> Result<(), String> {
+        for (key, scancode) in self.scancodes.iter_mut().enumerate() {
+            let name = keymap.name(key);
+            *scancode = match Scancode::from_name(name) {
+                Some(code) if !RESERVED.contains(&code) => code,
+                Some(_) => return Err(format!("{} is used by the emulator and can't be bound to key {:X}", name, key)),
+                None => return Err(format!("unknown key name {} for key {:X}", name, key)),
+            };
+        }
+        return Ok(());
+    }
 
+    /// Handle pending events, adding keypad presses and releases to `keys`
+    pub fn poll(&mut self, keys: &mut Vec<KeyEvent>) -> Command {
+        let mut command = Command::Continue;
+        let scancodes = self.scancodes;
+        let pad_key = |code| scancodes.iter().position(|c| *c == code).map(|k| k as u8);
         for event in self.event_pump.poll_iter() {
-            if let Event::Quit {..} = event {
-                return Command::Quit;
+            match event {
+                Event::Quit {..} => return Command::Quit,
+                Event::KeyDown { keycode: Some(Keycode::F12), repeat: false, .. } => command = Command::Rebind,
+                Event::KeyDown { keycode: Some(Keycode::F10), repeat: false, .. } => command = Command::NextFilter,
+                Event::KeyDown { keycode: Some(Keycode::F11), repeat: false, .. } => command = Command::ToggleFullscreen,
+                Event::Window { win_event: WindowEvent::SizeChanged(..), .. }
+                | Event::Window { win_event: WindowEvent::Exposed, .. } => {
+                    // Anything else asked for this poll matters more
+                    if let Command::Continue = command {
+                        command = Command::Redraw;
+                    }
+                },
+                Event::KeyDown { scancode: Some(code), repeat: false, .. } if pad_key(code).is_some() => {
+                    keys.push(KeyEvent::Down(pad_key(code).unwrap()));
+                },
+                Event::KeyUp { scancode: Some(code), .. } if pad_key(code).is_some() => {
+                    keys.push(KeyEvent::Up(pad_key(code).unwrap()));
+                },
+                Event::KeyDown { keycode: Some(key), keymod, repeat: false, .. } => {
+                    if let Some(i) = SLOT_KEYS.iter().position(|k| *k == key) {
+                        let slot = i as u8 + 1;
+                        command = if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                            Command::SaveState(slot)
+                        } else {
+                            Command::LoadState(slot)
+                        };
+                    }
+                },
+                _ => {},
             }
         }
 
-        let kb = KeyboardState::new(&self.event_pump);
-        keys[0x0] = kb.is_scancode_pressed(Scancode::Num0) as u8;
-        keys[0x1] = kb.is_scancode_pressed(Scancode::Num1) as u8;
-        keys[0x2] = kb.is_scancode_pressed(Scancode::Num2) as u8;
-        keys[0x3] = kb.is_scancode_pressed(Scancode::Num3) as u8;
-        keys[0x4] = kb.is_scancode_pressed(Scancode::Num4) as u8;
-        keys[0x5] = kb.is_scancode_pressed(Scancode::Num5) as u8;
-        keys[0x6] = kb.is_scancode_pressed(Scancode::Num6) as u8;
-        keys[0x7] = kb.is_scancode_pressed(Scancode::Num7) as u8;
-        keys[0x8] = kb.is_scancode_pressed(Scancode::Num8) as u8;
-        keys[0x9] = kb.is_scancode_pressed(Scancode::Num9) as u8;
-        keys[0xA] = kb.is_scancode_pressed(Scancode::A) as u8;
-        keys[0xB] = kb.is_scancode_pressed(Scancode::B) as u8;
-        keys[0xC] = kb.is_scancode_pressed(Scancode::C) as u8;
-        keys[0xD] = kb.is_scancode_pressed(Scancode::D) as u8;
-        keys[0xE] = kb.is_scancode_pressed(Scancode::E) as u8;
-        keys[0xF] = kb.is_scancode_pressed(Scancode::F) as u8;
-
-        return Command::Continue;
+        return command;
     }
-}
\ No newline at end of file
+
+    /// Backspace is held to rewind
+    pub fn rewind_held(&self) -> bool {
+        return KeyboardState::new(&self.event_pump).is_scancode_pressed(Scancode::Backspace);
+    }
+
+    /// The next key pressed since the last call, for the rebinding screen.
+    /// Keys the frontend reserves are skipped.
+    pub fn read_key(&mut self) -> KeyPress {
+        for event in self.event_pump.poll_iter() {
+            match event {
+                Event::Quit {..} => return KeyPress::Quit,
+                Event::KeyDown { scancode: Some(Scancode::Escape), .. } => return KeyPress::Cancel,
+                Event::KeyDown { scancode: Some(code), repeat: false, .. } if !RESERVED.contains(&code) => {
+                    return KeyPress::Key(code.name().to_string());
+                },
+                _ => {},
+            }
+        }
+        return KeyPress::Nothing;
+    }
+}
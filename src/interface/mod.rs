@@ -1,2 +1,12 @@
+#[cfg(feature = "sdl")]
+pub mod audio;
+#[cfg(feature = "sdl")]
+pub mod debugger;
+#[cfg(feature = "sdl")]
 pub mod display;
-pub mod input;
\ No newline at end of file
+#[cfg(feature = "sdl")]
+pub mod input;
+#[cfg(feature = "sdl")]
+pub mod text;
+#[cfg(feature = "tui")]
+pub mod tui;
@@ -0,0 +1,88 @@
+
+// 3x5 glyphs, one row per byte with the leftmost pixel in bit 2
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    return match c.to_ascii_uppercase() {
+        '0' => [7, 5, 5, 5, 7],
+        '1' => [2, 6, 2, 2, 7],
+        '2' => [7, 1, 7, 4, 7],
+        '3' => [7, 1, 3, 1, 7],
+        '4' => [5, 5, 7, 1, 1],
+        '5' => [7, 4, 7, 1, 7],
+        '6' => [7, 4, 7, 5, 7],
+        '7' => [7, 1, 1, 2, 2],
+        '8' => [7, 5, 7, 5, 7],
+        '9' => [7, 5, 7, 1, 7],
+        'A' => [2, 5, 7, 5, 5],
+        'B' => [6, 5, 6, 5, 6],
+        'C' => [3, 4, 4, 4, 3],
+        'D' => [6, 5, 5, 5, 6],
+        'E' => [7, 4, 6, 4, 7],
+        'F' => [7, 4, 6, 4, 4],
+        'G' => [3, 4, 5, 5, 3],
+        'H' => [5, 5, 7, 5, 5],
+        'I' => [7, 2, 2, 2, 7],
+        'J' => [1, 1, 1, 5, 2],
+        'K' => [5, 5, 6, 5, 5],
+        'L' => [4, 4, 4, 4, 7],
+        'M' => [5, 7, 7, 5, 5],
+        'N' => [6, 5, 5, 5, 5],
+        'O' => [2, 5, 5, 5, 2],
+        'P' => [6, 5, 6, 4, 4],
+        'Q' => [2, 5, 5, 6, 3],
+        'R' => [6, 5, 6, 5, 5],
+        'S' => [3, 4, 2, 1, 6],
+        'T' => [7, 2, 2, 2, 2],
+        'U' => [5, 5, 5, 5, 7],
+        'V' => [5, 5, 5, 5, 2],
+        'W' => [5, 5, 7, 7, 5],
+        'X' => [5, 5, 2, 5, 5],
+        'Y' => [5, 5, 2, 2, 2],
+        'Z' => [7, 1, 2, 4, 7],
+        ':' => [0, 2, 0, 2, 0],
+        '.' => [0, 0, 0, 0, 2],
+        ',' => [0, 0, 0, 2, 4],
+        '-' => [0, 0, 7, 0, 0],
+        '=' => [0, 7, 0, 7, 0],
+        '/' => [1, 1, 2, 4, 4],
+        '(' => [1, 2, 2, 2, 1],
+        ')' => [4, 2, 2, 2, 4],
+        '?' => [6, 1, 2, 0, 2],
+        _ => [0; GLYPH_HEIGHT],
+    };
+}
+
+/// Render lines of text into a `width` x `height` bitmap. Lines that don't fit
+/// are wrapped, anything past the bottom of the bitmap is dropped.
+pub fn render(text: &str, width: usize, height: usize) -> Vec<u8> {
+    let mut bitmap = vec![0; width * height];
+    let cols = width / (GLYPH_WIDTH + 1);
+
+    let mut row = 0;
+    for line in text.lines() {
+        let chars: Vec<char> = line.chars().collect();
+        if chars.is_empty() {
+            row += 1;
+        }
+        for chunk in chars.chunks(cols.max(1)) {
+            let top = row * (GLYPH_HEIGHT + 1) + 1;
+            if top + GLYPH_HEIGHT > height {
+                return bitmap;
+            }
+            for (col, c) in chunk.iter().enumerate() {
+                let left = col * (GLYPH_WIDTH + 1) + 1;
+                for (y, bits) in glyph(*c).iter().enumerate() {
+                    for x in 0..GLYPH_WIDTH {
+                        if bits & (0b100 >> x) != 0 && left + x < width {
+                            bitmap[(top + y) * width + left + x] = 1;
+                        }
+                    }
+                }
+            }
+            row += 1;
+        }
+    }
+    return bitmap;
+}
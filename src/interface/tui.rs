@@ -0,0 +1,145 @@
+use std::io::{self, Stdout, Write};
+use std::time::{Duration, Instant};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::{cursor, execute, queue, style, terminal};
+use chip8::headless;
+use chip8::keymap::Keymap;
+use chip8::{Chip8, KeyEvent};
+
+// Most terminals only report presses and auto-repeats, so a key counts as
+// held until this long after its last event. It needs to bridge the delay
+// before auto-repeat starts.
+const HOLD: Duration = Duration::from_millis(550);
+
+pub enum Command {
+    Continue,
+    Quit,
+}
+
+/// Draws the screen with Unicode characters and reads the keypad from the
+/// terminal, restoring the terminal when dropped
+pub struct Terminal {
+    out: Stdout,
+    braille: bool,
+    // The character driving each keypad key, lower case
+    chars: [char; 16],
+    // When each keypad key was last reported pressed
+    held: [Option<Instant>; 16],
+    // Whether the terminal reports key releases, so HOLD isn't needed
+    reports_release: bool,
+    sounding: bool,
+}
+
+impl Terminal {
+    /// `glyphs` is "blocks" or "braille". Terminals only report characters,
+    /// so each key in the keymap must be a single character or Space.
+    pub fn new(glyphs: &str, keymap: &Keymap) -> io::Result<Self> {
+        let mut chars = [' '; 16];
+        for (key, c) in chars.iter_mut().enumerate() {
+            let name = keymap.name(key);
+            let mut name_chars = name.chars();
+            *c = match (name_chars.next(), name_chars.next()) {
+                _ if name.eq_ignore_ascii_case("space") => ' ',
+                (Some(c), None) => c.to_ascii_lowercase(),
+                _ => return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("the terminal can't read {} for key {:X}", name, key),
+                )),
+            };
+        }
+
+        let mut out = io::stdout();
+        terminal::enable_raw_mode()?;
+        execute!(out, terminal::EnterAlternateScreen, cursor::Hide, terminal::Clear(terminal::ClearType::All))?;
+
+        let reports_release = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if reports_release {
+            execute!(out, PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES))?;
+        }
+
+        return Ok(Terminal {
+            out,
+            braille: glyphs == "braille",
+            chars,
+            held: [None; 16],
+            reports_release,
+            sounding: false,
+        });
+    }
+
+    /// Read pending key events, adding keypad presses and releases to `keys`.
+    /// Esc or Ctrl+C quits.
+    pub fn poll(&mut self, keys: &mut Vec<KeyEvent>) -> io::Result<Command> {
+        while event::poll(Duration::from_secs(0))? {
+            let key = match event::read()? {
+                Event::Key(key) => key,
+                _ => continue,
+            };
+            let quit = key.code == KeyCode::Esc
+                || (key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL));
+            if quit && key.kind != KeyEventKind::Release {
+                return Ok(Command::Quit);
+            }
+
+            if let KeyCode::Char(c) = key.code {
+                let c = c.to_ascii_lowercase();
+                if let Some(k) = self.chars.iter().position(|held| *held == c) {
+                    match (key.kind, self.held[k]) {
+                        (KeyEventKind::Release, Some(_)) => {
+                            self.held[k] = None;
+                            keys.push(KeyEvent::Up(k as u8));
+                        },
+                        (KeyEventKind::Release, None) => {},
+                        (_, held) => {
+                            if held.is_none() {
+                                keys.push(KeyEvent::Down(k as u8));
+                            }
+                            self.held[k] = Some(Instant::now());
+                        },
+                    }
+                }
+            }
+        }
+
+        if !self.reports_release {
+            for (key, held) in self.held.iter_mut().enumerate() {
+                if held.map(|since| since.elapsed() > HOLD).unwrap_or(false) {
+                    *held = None;
+                    keys.push(KeyEvent::Up(key as u8));
+                }
+            }
+        }
+        return Ok(Command::Continue);
+    }
+
+    pub fn draw(&mut self, inter: &Chip8) -> io::Result<()> {
+        let art = if self.braille { headless::braille(inter) } else { headless::half_blocks(inter) };
+        queue!(self.out, cursor::MoveTo(0, 0))?;
+        for (row, line) in art.lines().enumerate() {
+            queue!(self.out, cursor::MoveTo(0, row as u16), style::Print(line))?;
+        }
+        return self.out.flush();
+    }
+
+    /// Ring the terminal bell when the sound timer starts
+    pub fn beep(&mut self, inter: &Chip8) -> io::Result<()> {
+        if inter.sound_active() && !self.sounding {
+            queue!(self.out, style::Print('\u{7}'))?;
+        }
+        self.sounding = inter.sound_active();
+        return Ok(());
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        if self.reports_release {
+            let _ = execute!(self.out, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(self.out, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
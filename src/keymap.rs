@@ -0,0 +1,169 @@
+use crate::config::Config;
+
+pub const LAYOUTS: [&str; 2] = ["qwerty", "literal"];
+
+/// The keypad keys in the order they sit on the COSMAC VIP's 4x4 pad, row by
+/// row. The rebinding screen asks for them in this order.
+pub const PAD_ORDER: [usize; 16] = [
+    0x1, 0x2, 0x3, 0xC,
+    0x4, 0x5, 0x6, 0xD,
+    0x7, 0x8, 0x9, 0xE,
+    0xA, 0x0, 0xB, 0xF,
+];
+
+// The left hand block of a QWERTY keyboard, matching PAD_ORDER
+const QWERTY: [&str; 16] = [
+    "1", "2", "3", "4",
+    "Q", "W", "E", "R",
+    "A", "S", "D", "F",
+    "Z", "X", "C", "V",
+];
+
+/// Which host key drives each keypad key. Keys are named the way SDL names
+/// them, e.g. "Q", "1", "Space" or "Left", and compared ignoring case.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Keymap {
+    names: [String; 16],
+}
+
+impl Keymap {
+    /// `qwerty` lays the pad out over 1234/QWER/ASDF/ZXCV, `literal` uses the
+    /// keys printed on it, 0-9 and A-F
+    pub fn from_layout(name: &str) -> Option<Self> {
+        let mut names: [String; 16] = Default::default();
+        match name.to_ascii_lowercase().as_str() {
+            "qwerty" => {
+                for (key, host) in PAD_ORDER.iter().zip(QWERTY.iter()) {
+                    names[*key] = host.to_string();
+                }
+            },
+            "literal" => {
+                for (key, name) in names.iter_mut().enumerate() {
+                    *name = format!("{:X}", key);
+                }
+            },
+            _ => return None,
+        }
+        return Some(Keymap { names });
+    }
+
+    /// The layout named by `layout`, or else the `keymap` setting for `rom`,
+    /// with any `key_X = name` overrides from the config applied
+    pub fn from_config(config: &Config, rom: &str, layout: Option<&str>) -> Result<Self, String> {
+        let layout = layout.or_else(|| config.get(rom, "keymap")).unwrap_or(LAYOUTS[0]);
+        let mut keymap = Keymap::from_layout(layout).ok_or_else(|| {
+            format!("unknown keymap {}, expected one of {}", layout, LAYOUTS.join(", "))
+        })?;
+        for key in 0..16 {
+            if let Some(name) = config.get(rom, &config_key(key)) {
+                keymap.bind(key, name);
+            }
+        }
+        return Ok(keymap);
+    }
+
+    /// The host key bound to keypad key `key`
+    pub fn name(&self, key: usize) -> &str {
+        return &self.names[key];
+    }
+
+    /// Bind keypad key `key` to the host key `name`. A keypad key that was
+    /// already bound to it takes over the old binding, so no host key drives
+    /// two keypad keys.
+    pub fn bind(&mut self, key: usize, name: &str) {
+        if let Some(other) = self.key_for(name) {
+            self.names[other] = self.names[key].clone();
+        }
+        self.names[key] = name.to_string();
+    }
+
+    /// The keypad key driven by the host key `name`
+    pub fn key_for(&self, name: &str) -> Option<usize> {
+        return self.names.iter().position(|n| n.eq_ignore_ascii_case(name));
+    }
+
+    /// Write every binding to `rom`'s section of the config
+    pub fn save_to(&self, config: &mut Config, rom: &str) {
+        for (key, name) in self.names.iter().enumerate() {
+            config.set(Some(rom), &config_key(key), name);
+        }
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        return Keymap::from_layout(LAYOUTS[0]).unwrap();
+    }
+}
+
+/// The config entry overriding keypad key `key`, e.g. `key_A`
+pub fn config_key(key: usize) -> String {
+    return format!("key_{:X}", key);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_qwerty() {
+        let keymap = Keymap::default();
+        assert_eq!(keymap.name(0x1), "1");
+        assert_eq!(keymap.name(0xC), "4");
+        assert_eq!(keymap.name(0x4), "Q");
+        assert_eq!(keymap.name(0xA), "Z");
+        assert_eq!(keymap.name(0x0), "X");
+        assert_eq!(keymap.name(0xF), "V");
+        assert_eq!(keymap.key_for("s"), Some(0x8));
+    }
+
+    #[test]
+    fn test_literal() {
+        let keymap = Keymap::from_layout("literal").unwrap();
+        for key in 0..16 {
+            assert_eq!(keymap.key_for(&format!("{:x}", key)), Some(key));
+        }
+        assert!(Keymap::from_layout("dvorak").is_none());
+    }
+
+    #[test]
+    fn test_bind_swaps() {
+        let mut keymap = Keymap::default();
+        keymap.bind(0x5, "Space");
+        assert_eq!(keymap.key_for("space"), Some(0x5));
+        assert_eq!(keymap.key_for("W"), None);
+
+        // Taking a key already in use hands the old one over
+        keymap.bind(0x4, "Space");
+        assert_eq!(keymap.name(0x4), "Space");
+        assert_eq!(keymap.name(0x5), "Q");
+    }
+
+    #[test]
+    fn test_from_config() {
+        let config = Config::parse("keymap = literal\n\n[INVADERS]\nkeymap = qwerty\nkey_5 = Space\n").unwrap();
+
+        let keymap = Keymap::from_config(&config, "PONG", None).unwrap();
+        assert_eq!(keymap, Keymap::from_layout("literal").unwrap());
+
+        let keymap = Keymap::from_config(&config, "INVADERS", None).unwrap();
+        assert_eq!(keymap.name(0x5), "Space");
+        assert_eq!(keymap.name(0x4), "Q");
+
+        // The command line layout wins over the config's
+        let keymap = Keymap::from_config(&config, "PONG", Some("qwerty")).unwrap();
+        assert_eq!(keymap, Keymap::default());
+
+        let config = Config::parse("keymap = azerty\n").unwrap();
+        assert!(Keymap::from_config(&config, "PONG", None).is_err());
+    }
+
+    #[test]
+    fn test_save_to() {
+        let mut keymap = Keymap::from_layout("literal").unwrap();
+        keymap.bind(0x5, "Space");
+        let mut config = Config::default();
+        keymap.save_to(&mut config, "INVADERS");
+        assert_eq!(Keymap::from_config(&config, "INVADERS", Some("qwerty")).unwrap(), keymap);
+    }
+}
@@ -0,0 +1,30 @@
+#![allow(clippy::needless_return, non_snake_case)]
+
+//! Chip8 interpreter core.
+//!
+//! This crate has no dependency on any particular frontend, so it can be
+//! embedded in other tools. The SDL2 frontend lives in the `chip8-rs` binary
+//! and is only built with the `sdl` feature.
+
+extern crate bit_vec;
+extern crate rand;
+
+pub mod asm;
+pub mod config;
+pub mod disasm;
+pub mod filter;
+pub mod hardware;
+pub mod headless;
+pub mod keymap;
+pub mod movie;
+pub mod palette;
+pub mod rewind;
+pub mod scheduler;
+
+pub use crate::hardware::bus::{BusPolicy, Faults};
+pub use crate::hardware::chip8::{
+    version, Chip8, KeyEvent, DEFAULT_CYCLES_PER_FRAME, HEIGHT, HIRES_HEIGHT, HIRES_PIXEL_COUNT, HIRES_WIDTH, PIXEL_COUNT, WIDTH,
+};
+pub use crate::hardware::error::EmuError;
+pub use crate::hardware::instruction::{Instruction, Op, OPCODE_LEN};
+pub use crate::hardware::quirks::{IndexIncrement, Quirks, SpriteEdge};
@@ -1,26 +1,56 @@
 
-#![allow(warnings)]
+#![allow(clippy::needless_return)]
 
+extern crate chip8;
+#[cfg(feature = "sdl")]
 extern crate sdl2;
-extern crate bit_vec;
+#[cfg(feature = "tui")]
+extern crate crossterm;
 extern crate clap;
 extern crate rand;
 
-mod hardware;
 mod interface;
 
-use clap::{Arg, App};
+use clap::{Arg, App, AppSettings, SubCommand};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+#[cfg(any(feature = "sdl", feature = "tui"))]
+use std::thread;
+#[cfg(feature = "sdl")]
 use std::time::Duration;
 
-use crate::hardware::chip8;
-use crate::interface::{input, display};
+use chip8::{Chip8, EmuError};
+#[cfg(any(feature = "sdl", feature = "tui"))]
+use chip8::KeyEvent;
+#[cfg(any(feature = "sdl", feature = "tui"))]
+use chip8::config::Config;
+use chip8::filter;
+#[cfg(feature = "sdl")]
+use chip8::filter::Filter;
+use chip8::keymap;
+#[cfg(any(feature = "sdl", feature = "tui"))]
+use chip8::keymap::Keymap;
+use chip8::palette;
+#[cfg(feature = "sdl")]
+use chip8::palette::Palette;
+use chip8::movie::Movie;
+#[cfg(feature = "sdl")]
+use chip8::rewind::Rewind;
+use chip8::scheduler::FRAME_RATE;
+#[cfg(any(feature = "sdl", feature = "tui"))]
+use chip8::scheduler::Scheduler;
+use chip8::headless;
+#[cfg(feature = "sdl")]
+use crate::interface::{audio, debugger, input, display, text};
+#[cfg(feature = "tui")]
+use crate::interface::tui;
 
-static TITLE: &str = "GAME";
-static BEEP_TITLE: &str = "BEEP";
-
-const UI_SCALE: u32 = 8;
-const WIDTH: u32 = chip8::WIDTH * UI_SCALE;
-const HEIGHT: u32 = chip8::HEIGHT * UI_SCALE;
+const FRONTENDS: [&str; 2] = ["sdl", "tui"];
+const DEFAULT_FRONTEND: &str = if cfg!(feature = "sdl") { "sdl" } else { "tui" };
+// Kept out of the sdl feature so --help is the same in every build
+const SCALINGS: [&str; 2] = ["integer", "aspect"];
+const WAVEFORMS: [&str; 4] = ["square", "sine", "triangle", "sawtooth"];
 
 fn main() {
 
@@ -28,34 +58,831 @@ fn main() {
         .version(chip8::version())
         .author("Brian Grenier <grenierb96@gmail.com")
         .about("Emulates Chip8 programs")
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .subcommand(SubCommand::with_name("disasm")
+            .about("Print an assembly listing of a ROM")
+            .arg(Arg::with_name("ROM")
+                .help("Path name of the ROM to disassemble")
+                .required(true))
+            .arg(Arg::with_name("syntax")
+                .long("syntax")
+                .takes_value(true)
+                .possible_values(&chip8::disasm::SYNTAXES)
+                .default_value("cowgod")
+                .help("Assembly dialect to write"))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .help("File to write the listing to instead of stdout")))
+        .subcommand(SubCommand::with_name("asm")
+            .about("Assemble a source file into a ROM")
+            .arg(Arg::with_name("SOURCE")
+                .help("Path name of the source to assemble")
+                .required(true))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .help("File to write the ROM to, defaults to the source with a .ch8 extension")))
+        .subcommand(SubCommand::with_name("run")
+            .about("Run a ROM without a window or sound and dump the final screen")
+            .arg(Arg::with_name("ROM")
+                .help("Path name of the ROM to run")
+                .required(true))
+            .arg(Arg::with_name("headless")
+                .long("headless")
+                .required(true)
+                .help("Run with no video or audio"))
+            .args(&machine_args())
+            .arg(Arg::with_name("frames")
+                .long("frames")
+                .takes_value(true)
+                .default_value("600")
+                .help("Most frames to run for"))
+            .arg(Arg::with_name("until-pc")
+                .long("until-pc")
+                .takes_value(true)
+                .help("Stop when the PC reaches this hex address"))
+            .arg(Arg::with_name("until-loop")
+                .long("until-loop")
+                .help("Stop when the ROM jumps to itself or waits for a key"))
+            .arg(Arg::with_name("dump")
+                .long("dump")
+                .takes_value(true)
+                .possible_values(&headless::DUMP_FORMATS)
+                .default_value("ascii")
+                .help("Format to dump the final screen in"))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .required_if("dump", "png")
+                .help("File to write the screen to instead of stdout")))
         .arg(Arg::with_name("ROM")
             .help("Path name of the ROM to run")
             .required(true))
+        .args(&machine_args())
+        .arg(Arg::with_name("frontend")
+            .long("frontend")
+            .takes_value(true)
+            .possible_values(&FRONTENDS)
+            .default_value(DEFAULT_FRONTEND)
+            .help("Play in an SDL window or in the terminal"))
+        .arg(Arg::with_name("keymap")
+            .long("keymap")
+            .takes_value(true)
+            .possible_values(&keymap::LAYOUTS)
+            .help("Keyboard layout for the keypad, overrides the config file [default: qwerty]"))
+        .arg(Arg::with_name("config")
+            .long("config")
+            .takes_value(true)
+            .help("Settings file to use instead of ~/.config/chip8-rs/config.ini"))
+        .arg(Arg::with_name("palette")
+            .long("palette")
+            .takes_value(true)
+            .possible_values(&palette::PALETTES)
+            .help("Colours to draw with, overrides the config file [default: mono]"))
+        .arg(Arg::with_name("fg")
+            .long("fg")
+            .takes_value(true)
+            .help("Foreground colour as RRGGBB, replacing the palette's"))
+        .arg(Arg::with_name("bg")
+            .long("bg")
+            .takes_value(true)
+            .help("Background colour as RRGGBB, replacing the palette's"))
+        .arg(Arg::with_name("scale")
+            .long("scale")
+            .takes_value(true)
+            .default_value("8")
+            .help("Starting window size as a multiple of 64x32"))
+        .arg(Arg::with_name("scaling")
+            .long("scaling")
+            .takes_value(true)
+            .possible_values(&SCALINGS)
+            .default_value("integer")
+            .help("Fill a resized window in whole multiples or as far as the shape allows"))
+        .arg(Arg::with_name("filter")
+            .long("filter")
+            .takes_value(true)
+            .possible_values(&filter::FILTERS)
+            .default_value("none")
+            .help("Hide sprite flicker in the window, F10 switches between them"))
+        .arg(Arg::with_name("phosphor-decay")
+            .long("phosphor-decay")
+            .takes_value(true)
+            .default_value("150")
+            .help("Milliseconds pixels take to fade out with --filter phosphor"))
+        .arg(Arg::with_name("fullscreen")
+            .long("fullscreen")
+            .help("Start fullscreen, F11 switches back"))
+        .arg(Arg::with_name("glyphs")
+            .long("glyphs")
+            .takes_value(true)
+            .possible_values(&["blocks", "braille"])
+            .default_value("blocks")
+            .help("Characters the terminal frontend draws with"))
+        .arg(Arg::with_name("tone")
+            .long("tone")
+            .takes_value(true)
+            .default_value("440")
+            .help("Frequency of the beep in Hz"))
+        .arg(Arg::with_name("volume")
+            .long("volume")
+            .takes_value(true)
+            .default_value("25")
+            .help("Volume of the beep from 0 to 100"))
+        .arg(Arg::with_name("wave")
+            .long("wave")
+            .takes_value(true)
+            .possible_values(&WAVEFORMS)
+            .default_value("square")
+            .help("Shape of the beep"))
+        .arg(Arg::with_name("mute")
+            .long("mute")
+            .help("Disable sound"))
+        .arg(Arg::with_name("debug")
+            .long("debug")
+            .help("Start paused in the command line debugger"))
+        .arg(Arg::with_name("rewind")
+            .long("rewind")
+            .takes_value(true)
+            .default_value("600")
+            .help("Frames of history kept for rewinding with Backspace, 0 disables it"))
+        .arg(Arg::with_name("load-state")
+            .long("load-state")
+            .takes_value(true)
+            .help("Resume from a save state made with the same ROM"))
+        .arg(Arg::with_name("record-movie")
+            .long("record-movie")
+            .takes_value(true)
+            .conflicts_with_all(&["load-state", "play-movie"])
+            .help("Record the keys pressed each frame to a movie file"))
+        .arg(Arg::with_name("play-movie")
+            .long("play-movie")
+            .takes_value(true)
+            .conflicts_with("load-state")
+            .help("Play back a movie, its quirks, seed, speed and memory options override the ones given"))
+        .arg(Arg::with_name("verify")
+            .long("verify")
+            .requires("play-movie")
+            .help("Play the movie without a window and check it ends where the recording did"))
         .get_matches();
 
-    let mut inter = chip8::Chip8::default();
-    inter.load_program(matches.value_of("ROM").unwrap());
+    if let Some(matches) = matches.subcommand_matches("disasm") {
+        return disasm(matches);
+    }
+    if let Some(matches) = matches.subcommand_matches("asm") {
+        return asm(matches);
+    }
+    if let Some(matches) = matches.subcommand_matches("run") {
+        return run_headless(matches);
+    }
+
+    let rom = matches.value_of("ROM").unwrap();
+    if let Some(path) = matches.value_of("play-movie") {
+        if matches.is_present("verify") {
+            return verify_movie(rom, path);
+        }
+    }
+    match matches.value_of("frontend").unwrap() {
+        "tui" => play_tui(&matches, rom),
+        _ => play_sdl(&matches, rom),
+    }
+}
+
+#[cfg(not(feature = "sdl"))]
+fn play_sdl(_matches: &clap::ArgMatches, _rom: &str) {
+    eprintln!("Built without the sdl feature, try --frontend tui or run --headless");
+    process::exit(1);
+}
+
+#[cfg(not(feature = "tui"))]
+fn play_tui(_matches: &clap::ArgMatches, _rom: &str) {
+    eprintln!("Built without the tui feature, rebuild with --features tui");
+    process::exit(1);
+}
+
+/// Run the ROM in the terminal until Esc is pressed
+#[cfg(feature = "tui")]
+fn play_tui(matches: &clap::ArgMatches, rom: &str) {
+    // The debugger reads its commands from the terminal the game is drawn
+    // in, and there's no way to hold a key for rewinding
+    for name in ["debug", "rewind"].iter() {
+        if matches.occurrences_of(name) > 0 {
+            eprintln!("--{} needs the SDL frontend", name);
+            process::exit(1);
+        }
+    }
+    let (mut inter, mut recording, mut playback) = start_session(matches, rom);
+    let mute = matches.is_present("mute");
+    let (config, _) = load_config(matches);
+    let keymap = load_keymap(matches, &config, rom);
+    let mut fault = None;
+
+    let result = tui::Terminal::new(matches.value_of("glyphs").unwrap(), &keymap).and_then(|mut term| {
+        let mut scheduler = Scheduler::new();
+        let mut keys = Vec::new();
+        'main: loop {
+            let seen = keys.len();
+            if let tui::Command::Quit = term.poll(&mut keys)? {
+                break 'main;
+            }
+            if playback.is_some() {
+                keys.clear();
+            }
+            for event in keys[seen..].iter() {
+                inter.key_event(*event);
+            }
+
+            for _ in 0..scheduler.frames_due() {
+                movie_frame(&mut inter, &mut recording, &mut playback, &keys);
+                keys.clear();
+                if let Err(e) = inter.step_frame() {
+                    fault = Some(e);
+                    break 'main;
+                }
+                if inter.exited() {
+                    break 'main;
+                }
+            }
+
+            if !mute {
+                term.beep(&inter)?;
+            }
+            if inter.draw {
+                inter.draw = false;
+                term.draw(&inter)?;
+            }
+
+            thread::sleep(scheduler.until_next_frame());
+        }
+        return Ok(());
+    });
+
+    // The terminal is restored by now, so reports are readable. Ignored
+    // accesses add up on the machine until they're taken
+    if let Some(faults) = inter.take_faults() {
+        eprintln!("{}", faults);
+    }
+    if let Err(e) = result {
+        eprintln!("Terminal error: {}", e);
+        process::exit(1);
+    }
+    save_movie(matches, recording, &inter);
+    if let Some(e) = fault {
+        eprintln!("FAULT\n{}\n\n{}", e, inter.register_dump());
+        process::exit(1);
+    }
+}
+
+/// Run the ROM in a window until it's closed
+#[cfg(feature = "sdl")]
+fn play_sdl(matches: &clap::ArgMatches, rom: &str) {
+    let (mut inter, mut recording, mut playback) = start_session(matches, rom);
+    // Anything that jumps around in time would desync a movie
+    let movie_active = recording.is_some() || playback.is_some();
+
+    let (mut config, config_path) = load_config(matches);
+    let mut keymap = load_keymap(matches, &config, rom);
 
     let sdl_context = sdl2::init().unwrap();
-    let mut input = input::Input::new(&sdl_context);
+    let mut input = input::Input::new(&sdl_context, &keymap).unwrap_or_else(|e| {
+        eprintln!("Bad keymap: {}", e);
+        process::exit(1);
+    });
+    let mut beeper = if matches.is_present("mute") {
+        None
+    } else {
+        let settings = audio::ToneSettings {
+            frequency: parse_arg(matches, "tone"),
+            volume: parse_arg::<f32>(matches, "volume").clamp(0.0, 100.0) / 100.0,
+            waveform: audio::Waveform::from_name(matches.value_of("wave").unwrap()).unwrap(),
+        };
+        match audio::Beeper::new(&sdl_context, settings) {
+            Ok(beeper) => Some(beeper),
+            Err(e) => {
+                eprintln!("Sound disabled: {}", e);
+                None
+            }
+        }
+    };
 
+    let palette = Palette::from_config(
+        &config,
+        &Config::section_for(rom),
+        matches.value_of("palette"),
+        matches.value_of("fg"),
+        matches.value_of("bg"),
+    ).unwrap_or_else(|e| {
+        eprintln!("Bad colours: {}", e);
+        process::exit(1);
+    });
     let mut window = display::Display::new(&sdl_context,
-                    "Chip8 Emulator",
-                    WIDTH,
-                    HEIGHT);
+                    &format!("{} - Chip8", Config::section_for(rom)),
+                    parse_arg::<u32>(matches, "scale").max(1),
+                    &palette,
+                    matches.value_of("scaling").unwrap());
+    if matches.is_present("fullscreen") {
+        window.toggle_fullscreen();
+    }
+
+    let mut debugger = if matches.is_present("debug") {
+        Some(debugger::Debugger::new())
+    } else {
+        None
+    };
+
+    let mut filter = Filter::new(
+        filter::Mode::from_name(matches.value_of("filter").unwrap()).unwrap(),
+        parse_arg(matches, "phosphor-decay"),
+    );
+
+    let mut rewind = Rewind::new(if movie_active { 0 } else { parse_arg(matches, "rewind") });
+    let mut scheduler = Scheduler::new();
+    // Key events given to the machine since the last frame, for recording
+    let mut keys = Vec::new();
 
     'main: loop {
-        match input.poll(&mut inter.keys) {
+        // Whether the window needs presenting even if the screen is unchanged
+        let mut redraw = false;
+        let seen = keys.len();
+        let command = input.poll(&mut keys);
+        if playback.is_some() {
+            // The movie drives the keypad
+            keys.clear();
+        }
+        for event in keys[seen..].iter() {
+            inter.key_event(*event);
+        }
+
+        match command {
             input::Command::Quit => break 'main,
             input::Command::Continue => {},
+            input::Command::Redraw => redraw = true,
+            input::Command::ToggleFullscreen => {
+                window.toggle_fullscreen();
+                redraw = true;
+            },
+            input::Command::NextFilter => {
+                filter.set_mode(filter.mode().next());
+                println!("Display filter: {}", filter.mode().name());
+                inter.draw = true;
+            },
+            input::Command::SaveState(slot) => {
+                let path = state_path(rom, slot);
+                match fs::write(&path, inter.save_state()) {
+                    Ok(()) => println!("Saved state to {}", path),
+                    Err(e) => eprintln!("Failed to save state {}: {}", path, e),
+                }
+            },
+            input::Command::Rebind => {
+                match rebind_keys(&keymap, &mut window, &mut input) {
+                    Ok(new) => {
+                        keymap = new;
+                        input.set_keymap(&keymap).unwrap();
+                        save_keymap(&keymap, &mut config, config_path.as_ref(), rom);
+                        // Releases of keys held under the old bindings would be lost
+                        for key in 0..16 {
+                            if inter.keys()[key] != 0 && playback.is_none() {
+                                keys.push(KeyEvent::Up(key as u8));
+                                inter.key_up(key as u8);
+                            }
+                        }
+                    },
+                    Err(input::KeyPress::Quit) => break 'main,
+                    Err(_) => {},
+                }
+                inter.draw = true;
+            },
+            input::Command::LoadState(_) if movie_active => {
+                eprintln!("Save states can't be loaded while a movie is recording or playing");
+            },
+            input::Command::LoadState(slot) => {
+                let path = state_path(rom, slot);
+                match load_state(&mut inter, &path) {
+                    Ok(()) => {
+                        // History from before the load isn't this timeline
+                        rewind.clear();
+                        println!("Loaded state from {}", path);
+                    },
+                    Err(e) => eprintln!("Failed to load state {}: {}", path, e),
+                }
+            },
         }
 
-        inter.run_cycle();
+        let frames = scheduler.frames_due();
+        for _ in 0..frames {
+            if input.rewind_held() {
+                // The snapshot's keypad is from the past, put back the keys
+                // held right now so none stay pressed or released wrongly
+                let held = *inter.keys();
+                rewind.step_back(&mut inter);
+                inter.set_keys(&held);
+                keys.clear();
+                continue;
+            }
+            movie_frame(&mut inter, &mut recording, &mut playback, &keys);
+            keys.clear();
+
+            let result = match debugger.as_mut() {
+                Some(debugger) => debugger.run_frame(&mut inter, &mut |inter| {
+                    window.draw_frame(&inter.screen, inter.width(), inter.height());
+                }),
+                None => inter.step_frame().map(|_| true),
+            };
+            match result {
+                Ok(true) => {},
+                Ok(false) => break 'main,
+                Err(e) => {
+                    show_fault(&e, &inter, &mut window, &mut input);
+                    break 'main;
+                }
+            }
+            if inter.exited() {
+                break 'main;
+            }
+            rewind.record(&inter);
+        }
 
-        if inter.draw {
+        if let Some(beeper) = beeper.as_mut() {
+            beeper.update(&inter);
+        }
+        if let Some(faults) = inter.take_faults() {
+            eprintln!("{}", faults);
+        }
+
+        // The loop runs once per 60 Hz frame, so this presents at most that
+        // often however many times the ROM drew
+        if inter.draw || (frames > 0 && filter.changing()) {
             inter.draw = false;
-            window.draw_frame(&inter.screen);
+            if let Some(frame) = filter.next_frame(&inter) {
+                window.update(&frame);
+                redraw = true;
+            }
+        }
+        if redraw {
+            window.present();
+        }
+
+        thread::sleep(scheduler.until_next_frame());
+    }
+
+    save_movie(matches, recording, &inter);
+}
+
+/// The machine to play, from --play-movie or --load-state if given, and the
+/// movie being recorded or played with the next frame to play
+#[cfg(any(feature = "sdl", feature = "tui"))]
+fn start_session(matches: &clap::ArgMatches, rom: &str) -> (Chip8, Option<Movie>, Option<(Movie, usize)>) {
+    let (mut inter, seed) = load_machine(matches, rom);
+
+    let recording = matches.value_of("record-movie").map(|_| Movie::new(&inter, seed));
+    let playback = matches.value_of("play-movie").map(|path| {
+        let (movie, started) = start_movie(rom, path).unwrap_or_else(|e| {
+            eprintln!("Failed to play movie {}: {}", path, e);
+            process::exit(1);
+        });
+        inter = started;
+        (movie, 0)
+    });
+    if let Some(path) = matches.value_of("load-state") {
+        if let Err(e) = load_state(&mut inter, path) {
+            eprintln!("Failed to load state {}: {}", path, e);
+            process::exit(1);
+        }
+    }
+    return (inter, recording, playback);
+}
+
+/// Before each frame, press the movie's keys or record the ones just given
+#[cfg(any(feature = "sdl", feature = "tui"))]
+fn movie_frame(inter: &mut Chip8, recording: &mut Option<Movie>, playback: &mut Option<(Movie, usize)>, keys: &[KeyEvent]) {
+    if let Some((movie, frame)) = playback.as_mut() {
+        match movie.events(*frame) {
+            Some(events) => {
+                for event in events.iter() {
+                    inter.key_event(*event);
+                }
+                *frame += 1;
+            },
+            None => {
+                println!("Movie finished");
+                *playback = None;
+            },
+        }
+    }
+    if let Some(movie) = recording.as_mut() {
+        movie.record_frame(keys);
+    }
+}
+
+#[cfg(any(feature = "sdl", feature = "tui"))]
+fn save_movie(matches: &clap::ArgMatches, recording: Option<Movie>, inter: &Chip8) {
+    if let (Some(mut movie), Some(path)) = (recording, matches.value_of("record-movie")) {
+        movie.finish(inter);
+        match fs::write(path, movie.to_bytes()) {
+            Ok(()) => println!("Saved movie to {}", path),
+            Err(e) => eprintln!("Failed to save movie {}: {}", path, e),
+        }
+    }
+}
+
+/// Build a machine from the quirks, speed, memory and seed options and load
+/// the ROM, returning the seed used
+fn load_machine(matches: &clap::ArgMatches, rom: &str) -> (Chip8, u64) {
+    let mut quirks = chip8::Quirks::from_name(matches.value_of("quirks").unwrap()).unwrap();
+    quirks.index_overflow |= matches.is_present("index-overflow");
+    let mut inter = chip8::Chip8::new(quirks);
+    inter.set_cycles_per_frame(cycles_per_frame(matches));
+    inter.set_bus_policy(chip8::BusPolicy::from_name(matches.value_of("out-of-bounds").unwrap()).unwrap());
+    let seed = if matches.is_present("seed") {
+        parse_arg(matches, "seed")
+    } else {
+        rand::random()
+    };
+    inter.set_seed(seed);
+    if let Err(e) = inter.load_program(rom) {
+        eprintln!("Failed to load {}: {}", rom, e);
+        process::exit(1);
+    }
+    return (inter, seed);
+}
+
+/// The config file from --config or the default location, and where to save
+/// it. A malformed file is reported and ignored.
+#[cfg(any(feature = "sdl", feature = "tui"))]
+fn load_config(matches: &clap::ArgMatches) -> (Config, Option<PathBuf>) {
+    let path = matches.value_of("config").map(PathBuf::from).or_else(Config::default_path);
+    let config = match path.as_ref().map(|p| (p, Config::load(p))) {
+        Some((_, Ok(config))) => config,
+        Some((p, Err(e))) => {
+            eprintln!("Ignoring config {}: {}", p.display(), e);
+            Config::default()
+        },
+        None => Config::default(),
+    };
+    return (config, path);
+}
+
+#[cfg(any(feature = "sdl", feature = "tui"))]
+fn load_keymap(matches: &clap::ArgMatches, config: &Config, rom: &str) -> Keymap {
+    return Keymap::from_config(config, &Config::section_for(rom), matches.value_of("keymap")).unwrap_or_else(|e| {
+        eprintln!("Bad keymap: {}", e);
+        process::exit(1);
+    });
+}
+
+/// Options shared by the windowed and headless modes
+fn machine_args() -> [Arg<'static, 'static>; 6] {
+    return [
+        Arg::with_name("quirks")
+            .long("quirks")
+            .takes_value(true)
+            .possible_values(&chip8::hardware::quirks::PRESETS)
+            .default_value("default")
+            .help("Platform whose opcode quirks the ROM expects"),
+        Arg::with_name("index-overflow")
+            .long("index-overflow")
+            .help("FX1E sets VF when I passes the end of memory, as on the Amiga"),
+        Arg::with_name("out-of-bounds")
+            .long("out-of-bounds")
+            .takes_value(true)
+            .possible_values(&chip8::hardware::bus::POLICIES)
+            .default_value("error")
+            .help("What reading or writing past the end of memory does"),
+        Arg::with_name("ipf")
+            .long("ipf")
+            .takes_value(true)
+            .conflicts_with("hz")
+            .help("Instructions to run per 60 Hz frame"),
+        Arg::with_name("hz")
+            .long("hz")
+            .takes_value(true)
+            .help("CPU speed in instructions per second"),
+        Arg::with_name("seed")
+            .long("seed")
+            .takes_value(true)
+            .help("Seed for the random numbers from CXKK, random if not given"),
+    ];
+}
+
+fn run_headless(matches: &clap::ArgMatches) {
+    let rom = matches.value_of("ROM").unwrap();
+    let (mut inter, _) = load_machine(matches, rom);
+    let options = headless::Options {
+        max_frames: parse_arg(matches, "frames"),
+        until_pc: matches.value_of("until-pc").map(|addr| {
+            let digits = addr.trim_start_matches("0x").trim_start_matches("0X");
+            u16::from_str_radix(digits, 16).unwrap_or_else(|_| {
+                eprintln!("--until-pc must be a hex address, got {}", addr);
+                process::exit(1);
+            })
+        }),
+        until_loop: matches.is_present("until-loop"),
+    };
+
+    let (outcome, frames) = headless::run(&mut inter, &options);
+    if let Some(faults) = inter.take_faults() {
+        eprintln!("{}", faults);
+    }
+    match &outcome {
+        headless::Outcome::FramesElapsed => eprintln!("Stopped after {} frames", frames),
+        headless::Outcome::ReachedPc(pc) => eprintln!("Reached {:04X} after {} frames", pc, frames),
+        headless::Outcome::InfiniteLoop(pc) => eprintln!("Looping at {:04X} after {} frames", pc, frames),
+        headless::Outcome::WaitingForKey(pc) => eprintln!("Waiting for a key at {:04X} after {} frames", pc, frames),
+        headless::Outcome::Exited => eprintln!("Exited after {} frames", frames),
+        headless::Outcome::Fault(e) => eprintln!("FAULT\n{}\n\n{}", e, inter.register_dump()),
+    }
+
+    let dump = match matches.value_of("dump").unwrap() {
+        "png" => headless::png(&inter),
+        "blocks" => headless::half_blocks(&inter).into_bytes(),
+        "braille" => headless::braille(&inter).into_bytes(),
+        _ => headless::ascii(&inter).into_bytes(),
+    };
+    match matches.value_of("output") {
+        Some(path) => {
+            if let Err(e) = fs::write(path, dump) {
+                eprintln!("Failed to write {}: {}", path, e);
+                process::exit(1);
+            }
+        },
+        None => print!("{}", String::from_utf8_lossy(&dump)),
+    }
+    process::exit(outcome.exit_code(&options));
+}
+
+fn read_movie(rom: &str, path: &str) -> Result<(Movie, Vec<u8>), EmuError> {
+    let movie = Movie::from_bytes(&fs::read(path)?)?;
+    return Ok((movie, fs::read(rom)?));
+}
+
+#[cfg(any(feature = "sdl", feature = "tui"))]
+/// A machine set up as the movie was recorded, ready for its first frame
+fn start_movie(rom: &str, path: &str) -> Result<(Movie, Chip8), EmuError> {
+    let (movie, data) = read_movie(rom, path)?;
+    let inter = movie.start(&data)?;
+    return Ok((movie, inter));
+}
+
+/// Replay a movie as fast as possible and exit with 0 if it ended where the
+/// recording did
+fn verify_movie(rom: &str, path: &str) {
+    let result = read_movie(rom, path).and_then(|(movie, data)| {
+        let inter = movie.replay(&data)?;
+        return Ok((movie, inter));
+    });
+    match result {
+        Ok((movie, inter)) => {
+            if movie.final_hash.is_none() {
+                eprintln!("{} has no final hash to check against", path);
+                process::exit(1);
+            }
+            if !movie.verify(&inter) {
+                eprintln!("Mismatch: {} ended somewhere else after {} frames", path, movie.frames.len());
+                process::exit(1);
+            }
+            println!("OK: {} frames matched", movie.frames.len());
+        },
+        Err(e) => {
+            eprintln!("Failed to play movie {}: {}", path, e);
+            process::exit(1);
+        },
+    }
+}
+
+#[cfg(feature = "sdl")]
+/// Save slots live next to the ROM, e.g. PONG.state1
+fn state_path(rom: &str, slot: u8) -> String {
+    return format!("{}.state{}", rom, slot);
+}
+
+#[cfg(any(feature = "sdl", feature = "tui"))]
+fn load_state(inter: &mut Chip8, path: &str) -> Result<(), EmuError> {
+    let data = fs::read(path)?;
+    return inter.load_state(&data);
+}
+
+fn disasm(matches: &clap::ArgMatches) {
+    let rom = matches.value_of("ROM").unwrap();
+    let data = fs::read(rom).unwrap_or_else(|e| {
+        eprintln!("Failed to read {}: {}", rom, e);
+        process::exit(1);
+    });
+
+    let syntax = chip8::disasm::Syntax::from_name(matches.value_of("syntax").unwrap()).unwrap();
+    let listing = chip8::disasm::disassemble(&data, syntax);
+    match matches.value_of("output") {
+        Some(path) => {
+            if let Err(e) = fs::write(path, listing) {
+                eprintln!("Failed to write {}: {}", path, e);
+                process::exit(1);
+            }
+        },
+        None => print!("{}", listing),
+    }
+}
+
+fn asm(matches: &clap::ArgMatches) {
+    let source = Path::new(matches.value_of("SOURCE").unwrap());
+    let rom = chip8::asm::assemble_file(source).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+
+    let output = match matches.value_of("output") {
+        Some(path) => PathBuf::from(path),
+        None => source.with_extension("ch8"),
+    };
+    if let Err(e) = fs::write(&output, rom) {
+        eprintln!("Failed to write {}: {}", output.display(), e);
+        process::exit(1);
+    }
+}
+
+/// Parse a numeric argument, exiting with a message if it's malformed
+fn parse_arg<T: std::str::FromStr>(matches: &clap::ArgMatches, name: &str) -> T {
+    let value = matches.value_of(name).unwrap();
+    return value.parse().unwrap_or_else(|_| {
+        eprintln!("--{} must be a number, got {}", name, value);
+        process::exit(1);
+    });
+}
+
+fn cycles_per_frame(matches: &clap::ArgMatches) -> u32 {
+    if matches.is_present("ipf") {
+        return parse_arg(matches, "ipf");
+    }
+    if matches.is_present("hz") {
+        let hz: u32 = parse_arg(matches, "hz");
+        return (hz.saturating_add(FRAME_RATE / 2) / FRAME_RATE).max(1);
+    }
+    return chip8::DEFAULT_CYCLES_PER_FRAME;
+}
+
+/// Ask for a new host key for each keypad key in the pad's order. Esc
+/// cancels, leaving the keymap as it was.
+#[cfg(feature = "sdl")]
+fn rebind_keys(keymap: &Keymap, window: &mut display::Display, input: &mut input::Input) -> Result<Keymap, input::KeyPress> {
+    let width = chip8::HIRES_WIDTH as usize;
+    let height = chip8::HIRES_HEIGHT as usize;
+    let mut new = keymap.clone();
+
+    for key in keymap::PAD_ORDER.iter() {
+        let prompt = format!("PRESS A KEY FOR {:X}\n\nNOW: {}\n\nESC CANCELS", key, new.name(*key));
+        window.draw_frame(&text::render(&prompt, width, height), width, height);
+        loop {
+            match input.read_key() {
+                input::KeyPress::Nothing => thread::sleep(Duration::from_millis(16)),
+                input::KeyPress::Key(name) => {
+                    new.bind(*key, &name);
+                    break;
+                },
+                other => return Err(other),
+            }
         }
     }
+    return Ok(new);
+}
+
+/// Store the keymap in the ROM's section of the config file
+#[cfg(feature = "sdl")]
+fn save_keymap(keymap: &Keymap, config: &mut Config, path: Option<&PathBuf>, rom: &str) {
+    let path = match path {
+        Some(path) => path,
+        None => {
+            eprintln!("No config file to save the keys to, set --config");
+            return;
+        },
+    };
+    keymap.save_to(config, &Config::section_for(rom));
+    match config.save(path) {
+        Ok(()) => println!("Saved keys for {} to {}", rom, path.display()),
+        Err(e) => eprintln!("Failed to save config {}: {}", path.display(), e),
+    }
+}
+
+/// Replace the game with a report of the fault until the window is closed
+#[cfg(feature = "sdl")]
+fn show_fault(err: &EmuError, inter: &Chip8, window: &mut display::Display, input: &mut input::Input) {
+    let report = format!("FAULT\n{}\n\n{}", err, inter.register_dump());
+    eprintln!("{}", report);
 
+    let width = chip8::HIRES_WIDTH as usize;
+    let height = chip8::HIRES_HEIGHT as usize;
+    window.draw_frame(&text::render(&report, width, height), width, height);
+
+    loop {
+        match input.poll(&mut Vec::new()) {
+            input::Command::Quit => return,
+            input::Command::Redraw => window.draw_frame(&text::render(&report, width, height), width, height),
+            input::Command::ToggleFullscreen => {
+                window.toggle_fullscreen();
+                window.draw_frame(&text::render(&report, width, height), width, height);
+            },
+            _ => {},
+        }
+        thread::sleep(Duration::from_millis(16));
+    }
 }
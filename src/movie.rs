@@ -0,0 +1,289 @@
+use crate::hardware::bus::BusPolicy;
+use crate::hardware::chip8::{Chip8, KeyEvent};
+use crate::hardware::error::EmuError;
+use crate::hardware::quirks::Quirks;
+use crate::hardware::savestate::{self, Reader, Writer};
+
+/// Leads every movie file
+pub const MAGIC: &[u8; 4] = b"C8MV";
+
+/// Bumped whenever the layout changes
+pub const VERSION: u16 = 5;
+
+/// Keypad input for every frame of a run, along with everything else needed
+/// to reproduce it exactly from power on
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_hash: u64,
+    pub quirks: Quirks,
+    pub seed: u64,
+    pub cycles_per_frame: u32,
+    pub bus_policy: BusPolicy,
+    /// The key presses and releases delivered before each frame, in order
+    pub frames: Vec<Vec<KeyEvent>>,
+    /// `state_hash` of the machine after the last frame, if recording finished
+    pub final_hash: Option<u64>,
+}
+
+impl Movie {
+    /// Start recording a machine that's just loaded its ROM and been seeded
+    /// with `seed`
+    pub fn new(inter: &Chip8, seed: u64) -> Self {
+        return Movie {
+            rom_hash: inter.rom_hash(),
+            quirks: inter.quirks(),
+            seed,
+            cycles_per_frame: inter.cycles_per_frame(),
+            bus_policy: inter.bus_policy(),
+            frames: Vec::new(),
+            final_hash: None,
+        };
+    }
+
+    /// Record the key events given to the machine since the last frame, call
+    /// before running the next one
+    pub fn record_frame(&mut self, events: &[KeyEvent]) {
+        self.frames.push(events.to_vec());
+    }
+
+    /// Key events to deliver before `frame`, None past the end of the movie
+    pub fn events(&self, frame: usize) -> Option<&[KeyEvent]> {
+        return self.frames.get(frame).map(|events| events.as_slice());
+    }
+
+    /// Store the hash of the machine once recording is done
+    pub fn finish(&mut self, inter: &Chip8) {
+        self.final_hash = Some(state_hash(inter));
+    }
+
+    /// A machine set up the way the movie was recorded, with `rom` loaded
+    pub fn start(&self, rom: &[u8]) -> Result<Chip8, EmuError> {
+        let mut inter = Chip8::new(self.quirks);
+        inter.set_cycles_per_frame(self.cycles_per_frame);
+        inter.set_bus_policy(self.bus_policy);
+        inter.set_seed(self.seed);
+        inter.load_rom(rom)?;
+        if inter.rom_hash() != self.rom_hash {
+            return Err(EmuError::BadMovie(String::from("movie was recorded with a different ROM")));
+        }
+        return Ok(inter);
+    }
+
+    /// Play every frame of the movie without a frontend, stopping early if
+    /// the program exits
+    pub fn replay(&self, rom: &[u8]) -> Result<Chip8, EmuError> {
+        let mut inter = self.start(rom)?;
+        for frame in 0..self.frames.len() {
+            if inter.exited() {
+                break;
+            }
+            for event in self.frames[frame].iter() {
+                inter.key_event(*event);
+            }
+            inter.step_frame()?;
+        }
+        return Ok(inter);
+    }
+
+    /// Whether `inter` ended up where the recording did, false if no final
+    /// hash was stored
+    pub fn verify(&self, inter: &Chip8) -> bool {
+        return self.final_hash == Some(state_hash(inter));
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = Writer::new();
+        w.bytes(MAGIC);
+        w.u16(VERSION);
+        w.u64(self.rom_hash);
+        w.quirks(&self.quirks);
+        w.u64(self.seed);
+        w.u32(self.cycles_per_frame);
+        w.bus_policy(self.bus_policy);
+        w.bool(self.final_hash.is_some());
+        w.u64(self.final_hash.unwrap_or(0));
+        w.u32(self.frames.len() as u32);
+        for events in self.frames.iter() {
+            w.u16(events.len() as u16);
+            for event in events.iter() {
+                // The key in the low nibble, bit 7 set for a press
+                w.u8(match *event {
+                    KeyEvent::Down(key) => 0x80 | key,
+                    KeyEvent::Up(key) => key,
+                });
+            }
+        }
+        return w.data;
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, EmuError> {
+        let mut r = Reader::new(data);
+        if r.bytes(MAGIC.len()).map_err(not_a_movie)? != MAGIC {
+            return Err(not_a_movie(()));
+        }
+        let version = r.u16().map_err(not_a_movie)?;
+        if version != VERSION {
+            return Err(EmuError::BadMovie(format!(
+                "movie is version {}, this build reads version {}", version, VERSION
+            )));
+        }
+
+        let rom_hash = r.u64().map_err(not_a_movie)?;
+        let quirks = r.quirks().map_err(not_a_movie)?;
+        let seed = r.u64().map_err(not_a_movie)?;
+        let cycles_per_frame = r.u32().map_err(not_a_movie)?;
+        let bus_policy = r.bus_policy().map_err(not_a_movie)?;
+        let finished = r.bool().map_err(not_a_movie)?;
+        let hash = r.u64().map_err(not_a_movie)?;
+        let count = r.u32().map_err(not_a_movie)?;
+        let mut frames = Vec::new();
+        for _ in 0..count {
+            let len = r.u16().map_err(not_a_movie)?;
+            let mut events = Vec::new();
+            for _ in 0..len {
+                events.push(match r.u8().map_err(not_a_movie)? {
+                    byte @ 0x80..=0x8F => KeyEvent::Down(byte & 0xF),
+                    byte @ 0x00..=0x0F => KeyEvent::Up(byte),
+                    _ => return Err(not_a_movie(())),
+                });
+            }
+            frames.push(events);
+        }
+        if !r.is_empty() {
+            return Err(not_a_movie(()));
+        }
+
+        return Ok(Movie {
+            rom_hash,
+            quirks,
+            seed,
+            cycles_per_frame,
+            bus_policy,
+            frames,
+            final_hash: if finished { Some(hash) } else { None },
+        });
+    }
+}
+
+fn not_a_movie<T>(_: T) -> EmuError {
+    return EmuError::BadMovie(String::from("not a valid movie"));
+}
+
+/// Hash of the screen and memory, used to check a replay ends up in the same
+/// place as the recording
+pub fn state_hash(inter: &Chip8) -> u64 {
+    let mut data = inter.memory().to_vec();
+    data.extend_from_slice(&inter.screen);
+    return savestate::rom_hash(&data);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Draws the glyph of every held key at a random row, forever
+    const ROM: [u8; 22] = [
+        0x61, 0x00, // LD V1, 0
+        0xC3, 0x07, // RND V3, 0x07
+        0x60, 0x00, // LD V0, 0
+        0xF0, 0x29, // LD F, V0
+        0xE0, 0xA1, // SKNP V0
+        0xD1, 0x35, // DRW V1, V3, 5
+        0x71, 0x04, // ADD V1, 4
+        0x70, 0x01, // ADD V0, 1
+        0x30, 0x10, // SE V0, 16
+        0x12, 0x06, // JP 0x206
+        0x12, 0x00, // JP 0x200
+    ];
+
+    /// Record a run of `rom` where `inputs` are the keys held each frame,
+    /// given to the machine as presses and releases
+    fn record_rom(rom: &[u8], inputs: &[u16]) -> (Movie, Chip8) {
+        let mut inter = Chip8::new(Quirks::VIP);
+        inter.load_rom(rom).unwrap();
+        inter.set_seed(77);
+        let mut movie = Movie::new(&inter, 77);
+        let mut held = 0;
+        for mask in inputs.iter() {
+            let events: Vec<KeyEvent> = (0..16u8).filter(|key| (held ^ mask) >> key & 1 != 0)
+                .map(|key| if mask >> key & 1 != 0 { KeyEvent::Down(key) } else { KeyEvent::Up(key) })
+                .collect();
+            held = *mask;
+            for event in events.iter() {
+                inter.key_event(*event);
+            }
+            movie.record_frame(&events);
+            inter.step_frame().unwrap();
+        }
+        movie.finish(&inter);
+        return (movie, inter);
+    }
+
+    fn record(inputs: &[u16]) -> (Movie, Chip8) {
+        return record_rom(&ROM, inputs);
+    }
+
+    #[test]
+    fn test_events() {
+        let mut movie = Movie::new(&Chip8::default(), 0);
+        movie.record_frame(&[KeyEvent::Down(0), KeyEvent::Up(0xF)]);
+        assert_eq!(movie.events(0), Some(&[KeyEvent::Down(0), KeyEvent::Up(0xF)][..]));
+        assert_eq!(movie.events(1), None);
+    }
+
+    #[test]
+    fn test_replays_taps_within_a_frame() {
+        // LD V0, K then loop
+        let rom = [0xF0, 0x0A, 0x12, 0x02];
+        let mut inter = Chip8::new(Quirks::VIP);
+        inter.load_rom(&rom).unwrap();
+        let mut movie = Movie::new(&inter, 0);
+        movie.record_frame(&[]);
+        movie.record_frame(&[KeyEvent::Down(0xF), KeyEvent::Up(0xF)]);
+        movie.record_frame(&[]);
+
+        let replayed = movie.replay(&rom).unwrap();
+        assert_eq!(replayed.registers()[0], 0xF);
+        assert_eq!(replayed.pc(), 0x202);
+    }
+
+    #[test]
+    fn test_replay_matches_recording() {
+        let (movie, recorded) = record(&[0, 0x0002, 0x8000, 0x0001, 0, 0xFFFF]);
+        let replayed = movie.replay(&ROM).unwrap();
+        assert_eq!(replayed.save_state(), recorded.save_state());
+        assert!(movie.verify(&replayed));
+    }
+
+    #[test]
+    fn test_verify_detects_different_input() {
+        let (mut movie, _) = record(&[0x0002, 0x0004, 0x0008]);
+        movie.frames[1] = vec![KeyEvent::Down(4)];
+        let replayed = movie.replay(&ROM).unwrap();
+        assert!(!movie.verify(&replayed));
+    }
+
+    #[test]
+    fn test_file_round_trip() {
+        let (movie, _) = record(&[1, 2, 3, 0x8000, 0]);
+        assert_eq!(Movie::from_bytes(&movie.to_bytes()).unwrap(), movie);
+    }
+
+    #[test]
+    fn test_rejects_other_rom() {
+        let (movie, _) = record(&[1]);
+        assert!(matches!(movie.start(&[0x00, 0xE0]), Err(EmuError::BadMovie(_))));
+    }
+
+    #[test]
+    fn test_rejects_bad_files() {
+        let (movie, _) = record(&[1]);
+        let mut data = movie.to_bytes();
+        assert!(matches!(Movie::from_bytes(&data[..data.len() - 1]), Err(EmuError::BadMovie(_))));
+        data[5] += 1;
+        match Movie::from_bytes(&data) {
+            Err(EmuError::BadMovie(message)) => assert!(message.contains("version")),
+            other => panic!("expected a version error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use crate::config::Config;
+
+/// Names accepted by `Palette::from_name`
+pub const PALETTES: [&str; 5] = ["mono", "green", "amber", "lcd", "octo"];
+
+/// Red, green and blue
+pub type Rgb = [u8; 3];
+
+/// Colours for each combination of the two XO-CHIP planes: neither, the
+/// first, the second and both. Plain Chip8 only uses the first two.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub colours: [Rgb; 4],
+}
+
+impl Palette {
+    /// `fg` on `bg`, with the other two planes drawn as shades between them
+    pub fn two_tone(fg: Rgb, bg: Rgb) -> Self {
+        let mix = |parts: u16| {
+            let mut colour = [0; 3];
+            for (i, c) in colour.iter_mut().enumerate() {
+                *c = ((bg[i] as u16 * (3 - parts) + fg[i] as u16 * parts) / 3) as u8;
+            }
+            return colour;
+        };
+        return Palette { colours: [bg, fg, mix(2), mix(1)] };
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        return match name.to_ascii_lowercase().as_str() {
+            "mono" => Some(Palette::two_tone([0xFF, 0xFF, 0xFF], [0x00, 0x00, 0x00])),
+            // P1 phosphor green and P3 amber monitors
+            "green" => Some(Palette::two_tone([0x33, 0xFF, 0x66], [0x00, 0x1A, 0x08])),
+            "amber" => Some(Palette::two_tone([0xFF, 0xB0, 0x00], [0x1A, 0x0E, 0x00])),
+            "lcd" => Some(Palette::two_tone([0x0F, 0x38, 0x0F], [0x9B, 0xBC, 0x0F])),
+            // Octo's defaults, which XO-CHIP games are usually designed for
+            "octo" => Some(Palette {
+                colours: [[0x99, 0x66, 0x00], [0xFF, 0xCC, 0x00], [0xFF, 0x66, 0x00], [0x66, 0x22, 0x00]],
+            }),
+            _ => None,
+        };
+    }
+
+    /// The preset named by `name`, or else the `palette` setting for `rom`.
+    /// `fg` and `bg`, or the settings of the same names, replace its first
+    /// two colours.
+    pub fn from_config(config: &Config, rom: &str, name: Option<&str>, fg: Option<&str>, bg: Option<&str>) -> Result<Self, String> {
+        let name = name.or_else(|| config.get(rom, "palette")).unwrap_or(PALETTES[0]);
+        let palette = Palette::from_name(name).ok_or_else(|| {
+            format!("unknown palette {}, expected one of {}", name, PALETTES.join(", "))
+        })?;
+
+        let fg = fg.or_else(|| config.get(rom, "fg"));
+        let bg = bg.or_else(|| config.get(rom, "bg"));
+        if fg.is_none() && bg.is_none() {
+            return Ok(palette);
+        }
+        let fg = match fg {
+            Some(fg) => parse_colour(fg)?,
+            None => palette.colours[1],
+        };
+        let bg = match bg {
+            Some(bg) => parse_colour(bg)?,
+            None => palette.colours[0],
+        };
+        return Ok(Palette::two_tone(fg, bg));
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        return Palette::from_name(PALETTES[0]).unwrap();
+    }
+}
+
+/// Parse a hex colour written as RRGGBB, #RRGGBB or 0xRRGGBB
+pub fn parse_colour(text: &str) -> Result<Rgb, String> {
+    // One optional prefix, then exactly six hex digits
+    let digits = text.strip_prefix('#')
+        .or_else(|| text.strip_prefix("0x"))
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+    if digits.len() != 6 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("{} isn't a colour, expected RRGGBB", text));
+    }
+    let value = u32::from_str_radix(digits, 16).unwrap();
+    return Ok([(value >> 16) as u8, (value >> 8) as u8, value as u8]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mono_matches_old_greys() {
+        let palette = Palette::default();
+        assert_eq!(palette.colours, [[0; 3], [255; 3], [170; 3], [85; 3]]);
+    }
+
+    #[test]
+    fn test_presets_all_parse() {
+        for name in PALETTES.iter() {
+            assert!(Palette::from_name(name).is_some());
+        }
+        assert!(Palette::from_name("purple").is_none());
+    }
+
+    #[test]
+    fn test_parse_colour() {
+        assert_eq!(parse_colour("#FFB000"), Ok([0xFF, 0xB0, 0x00]));
+        assert_eq!(parse_colour("33ff66"), Ok([0x33, 0xFF, 0x66]));
+        assert_eq!(parse_colour("0x000102"), Ok([0, 1, 2]));
+        assert!(parse_colour("#FFF").is_err());
+        assert!(parse_colour("orange").is_err());
+        assert!(parse_colour("+FFFFF").is_err());
+        assert!(parse_colour("#0x123456").is_err());
+        assert!(parse_colour("##123456").is_err());
+    }
+
+    #[test]
+    fn test_from_config() {
+        let config = Config::parse("palette = amber\n\n[PONG]\nfg = #00FF00\n").unwrap();
+        assert_eq!(Palette::from_config(&config, "INVADERS", None, None, None), Ok(Palette::from_name("amber").unwrap()));
+        assert_eq!(Palette::from_config(&config, "INVADERS", Some("green"), None, None), Ok(Palette::from_name("green").unwrap()));
+
+        // A custom colour keeps the other one from the preset
+        let pong = Palette::from_config(&config, "PONG", None, None, None).unwrap();
+        assert_eq!(pong.colours[1], [0x00, 0xFF, 0x00]);
+        assert_eq!(pong.colours[0], Palette::from_name("amber").unwrap().colours[0]);
+
+        let custom = Palette::from_config(&config, "PONG", None, Some("FF0000"), Some("0000FF")).unwrap();
+        assert_eq!(custom, Palette::two_tone([0xFF, 0, 0], [0, 0, 0xFF]));
+
+        assert!(Palette::from_config(&config, "PONG", Some("purple"), None, None).is_err());
+        assert!(Palette::from_config(&config, "PONG", None, Some("red"), None).is_err());
+    }
+}
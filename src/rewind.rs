@@ -0,0 +1,215 @@
+use std::collections::VecDeque;
+use crate::hardware::chip8::Chip8;
+
+/// Ten seconds of frames
+pub const DEFAULT_FRAMES: usize = 600;
+
+/// Ring buffer of per-frame save states for stepping backwards in time.
+///
+/// Only the newest state is kept whole. Each older frame is stored as the
+/// XOR of it and the frame after, run length encoded, so the unchanged bulk
+/// of memory and screen costs a few bytes per frame.
+pub struct Rewind {
+    capacity: usize,
+    latest: Option<Vec<u8>>,
+    // Oldest first, each undoes one frame from the one after it
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl Rewind {
+    /// Keep up to `frames` frames of history, 0 disables rewinding
+    pub fn new(frames: usize) -> Self {
+        return Rewind {
+            capacity: frames,
+            latest: None,
+            deltas: VecDeque::new(),
+        };
+    }
+
+    /// Number of frames that can be stepped back
+    pub fn len(&self) -> usize {
+        return self.deltas.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.deltas.is_empty();
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+    }
+
+    /// Snapshot the machine, call once per frame
+    pub fn record(&mut self, inter: &Chip8) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let state = inter.save_state();
+        if let Some(latest) = self.latest.take() {
+            self.deltas.push_back(encode_delta(&state, &latest));
+            if self.deltas.len() > self.capacity {
+                self.deltas.pop_front();
+            }
+        }
+        self.latest = Some(state);
+    }
+
+    /// Restore the frame before the last recorded one, false once the history
+    /// runs out
+    pub fn step_back(&mut self, inter: &mut Chip8) -> bool {
+        let (latest, delta) = match (self.latest.as_ref(), self.deltas.pop_back()) {
+            (Some(latest), Some(delta)) => (latest, delta),
+            _ => return false,
+        };
+        let previous = apply_delta(latest, &delta);
+        if inter.load_state(&previous).is_err() {
+            self.clear();
+            return false;
+        }
+        self.latest = Some(previous);
+        return true;
+    }
+}
+
+/// Encode what turns `from` into `to` as runs of unchanged bytes followed by
+/// XORed ones: [target len] ([skip] [len] [bytes])*, counts are big endian u32
+fn encode_delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    delta.extend_from_slice(&(to.len() as u32).to_be_bytes());
+
+    let xor: Vec<u8> = (0..to.len())
+        .map(|i| to[i] ^ from.get(i).copied().unwrap_or(0))
+        .collect();
+    let mut i = 0;
+    while i < xor.len() {
+        let start = i;
+        while i < xor.len() && xor[i] == 0 {
+            i += 1;
+        }
+        let skip = i - start;
+        let changed = i;
+        while i < xor.len() && xor[i] != 0 {
+            i += 1;
+        }
+        if i == changed {
+            break;
+        }
+        delta.extend_from_slice(&(skip as u32).to_be_bytes());
+        delta.extend_from_slice(&((i - changed) as u32).to_be_bytes());
+        delta.extend_from_slice(&xor[changed..i]);
+    }
+    return delta;
+}
+
+fn apply_delta(from: &[u8], delta: &[u8]) -> Vec<u8> {
+    let read = |at: usize| u32::from_be_bytes([delta[at], delta[at + 1], delta[at + 2], delta[at + 3]]) as usize;
+
+    let mut to: Vec<u8> = from.to_vec();
+    to.resize(read(0), 0);
+    let mut at = 4;
+    let mut pos = 0;
+    while at < delta.len() {
+        pos += read(at);
+        let len = read(at + 4);
+        at += 8;
+        for (byte, change) in to[pos..pos + len].iter_mut().zip(delta[at..at + len].iter()) {
+            *byte ^= *change;
+        }
+        pos += len;
+        at += len;
+    }
+    return to;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hardware::quirks::Quirks;
+
+    // Counts V0 up forever, drawing as it goes
+    const ROM: [u8; 8] = [0x70, 0x01, 0xA2, 0x00, 0xD0, 0x01, 0x12, 0x00];
+
+    fn machine() -> Chip8 {
+        let mut inter = Chip8::new(Quirks::VIP);
+        inter.load_rom(&ROM).unwrap();
+        inter.set_cycles_per_frame(3);
+        inter.set_seed(0);
+        return inter;
+    }
+
+    #[test]
+    fn test_delta_round_trip() {
+        let from = vec![1, 2, 3, 4, 5, 6];
+        let to = vec![1, 9, 3, 4, 0, 0, 7];
+        assert_eq!(apply_delta(&from, &encode_delta(&from, &to)), to);
+        assert_eq!(apply_delta(&to, &encode_delta(&to, &from)), from);
+        assert_eq!(encode_delta(&from, &from), vec![0, 0, 0, 6]);
+    }
+
+    #[test]
+    fn test_step_back() {
+        let mut inter = machine();
+        let mut rewind = Rewind::new(DEFAULT_FRAMES);
+        let mut states = Vec::new();
+        for _ in 0..5 {
+            rewind.record(&inter);
+            states.push(inter.save_state());
+            inter.step_frame().unwrap();
+        }
+        rewind.record(&inter);
+        assert_eq!(rewind.len(), 5);
+
+        for expected in states.iter().rev() {
+            assert!(rewind.step_back(&mut inter));
+            assert_eq!(&inter.save_state(), expected);
+        }
+        assert!(!rewind.step_back(&mut inter));
+        assert_eq!(inter.registers()[0], 0);
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut inter = machine();
+        let mut rewind = Rewind::new(3);
+        for _ in 0..10 {
+            rewind.record(&inter);
+            inter.step_frame().unwrap();
+        }
+        assert_eq!(rewind.len(), 3);
+        let mut steps = 0;
+        while rewind.step_back(&mut inter) {
+            steps += 1;
+        }
+        assert_eq!(steps, 3);
+
+        // The last record was frame 9, so three steps back is frame 6
+        let mut expected = machine();
+        for _ in 0..6 {
+            expected.step_frame().unwrap();
+        }
+        assert_eq!(inter.save_state(), expected.save_state());
+    }
+
+    #[test]
+    fn test_deltas_are_small() {
+        let mut inter = machine();
+        let mut rewind = Rewind::new(DEFAULT_FRAMES);
+        rewind.record(&inter);
+        inter.step_frame().unwrap();
+        rewind.record(&inter);
+        assert!(rewind.deltas[0].len() < inter.save_state().len() / 10);
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut inter = machine();
+        let mut rewind = Rewind::new(0);
+        rewind.record(&inter);
+        inter.step_frame().unwrap();
+        rewind.record(&inter);
+        assert!(rewind.is_empty());
+        assert!(!rewind.step_back(&mut inter));
+    }
+}
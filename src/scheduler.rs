@@ -0,0 +1,92 @@
+use std::time::{Duration, Instant};
+
+pub const FRAME_RATE: u32 = 60;
+
+// Frames run at most per call to catch up after a stall, so a long pause
+// (e.g. the window being dragged) doesn't fast forward the game
+const MAX_CATCH_UP: u32 = 4;
+
+/// Paces emulation against wall-clock time by working out how many 60 Hz
+/// frames are due. Pair it with `Chip8::step_frame`.
+pub struct Scheduler {
+    last: Instant,
+    lag: Duration,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        return Scheduler {
+            last: Instant::now(),
+            lag: Duration::from_secs(0),
+        };
+    }
+
+    pub fn frame_duration() -> Duration {
+        return Duration::from_secs(1) / FRAME_RATE;
+    }
+
+    /// Number of frames that should run now
+    pub fn frames_due(&mut self) -> u32 {
+        let now = Instant::now();
+        let elapsed = now - self.last;
+        self.last = now;
+        return self.advance(elapsed);
+    }
+
+    /// Account for `elapsed` time passing and return how many frames it covers
+    pub fn advance(&mut self, elapsed: Duration) -> u32 {
+        let frame = Scheduler::frame_duration();
+        self.lag += elapsed;
+
+        let mut frames = 0;
+        while self.lag >= frame {
+            self.lag -= frame;
+            frames += 1;
+        }
+
+        if frames > MAX_CATCH_UP {
+            return MAX_CATCH_UP;
+        }
+        return frames;
+    }
+
+    /// How long until the next frame is due
+    pub fn until_next_frame(&self) -> Duration {
+        let waited = self.lag + self.last.elapsed();
+        return Scheduler::frame_duration().checked_sub(waited).unwrap_or_default();
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        return Scheduler::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_advance_less_than_frame() {
+        let mut scheduler = Scheduler::new();
+        assert_eq!(scheduler.advance(Duration::from_millis(10)), 0);
+        assert_eq!(scheduler.advance(Duration::from_millis(10)), 1);
+    }
+
+    #[test]
+    fn test_advance_one_second() {
+        let mut scheduler = Scheduler::new();
+        let mut frames = 0;
+        for _ in 0..100 {
+            frames += scheduler.advance(Duration::from_millis(10));
+        }
+        assert_eq!(frames, 60);
+    }
+
+    #[test]
+    fn test_advance_catch_up_limit() {
+        let mut scheduler = Scheduler::new();
+        assert_eq!(scheduler.advance(Duration::from_secs(1)), MAX_CATCH_UP);
+    }
+}
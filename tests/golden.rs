@@ -0,0 +1,64 @@
+//! Runs the bundled test ROMs headlessly and compares their final screens to
+//! the ASCII art in tests/golden.
+//!
+//! When behaviour changes on purpose, regenerate the goldens with
+//!
+//!     UPDATE_GOLDENS=1 cargo test --no-default-features --test golden
+//!
+//! and check the diff shows the ROMs still reporting success.
+
+#![allow(clippy::needless_return)]
+
+extern crate chip8;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use chip8::headless::{self, Options, Outcome};
+use chip8::{Chip8, Quirks};
+
+fn golden_path(name: &str) -> PathBuf {
+    return PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden").join(format!("{}.txt", name));
+}
+
+fn check(rom: &str, name: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("programs").join(rom);
+    let mut inter = Chip8::new(Quirks::default());
+    inter.set_seed(0);
+    inter.load_program(path.to_str().unwrap()).unwrap();
+
+    let options = Options { until_loop: true, ..Options::default() };
+    let (outcome, _) = headless::run(&mut inter, &options);
+    match outcome {
+        Outcome::InfiniteLoop(_) | Outcome::WaitingForKey(_) => {},
+        other => panic!("{} never settled: {:?}", rom, other),
+    }
+
+    let screen = headless::ascii(&inter);
+    let golden = golden_path(name);
+    if env::var_os("UPDATE_GOLDENS").is_some() {
+        fs::create_dir_all(golden.parent().unwrap()).unwrap();
+        fs::write(&golden, &screen).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(&golden).unwrap_or_else(|e| {
+        panic!("Couldn't read {}, set UPDATE_GOLDENS=1 to create it: {}", golden.display(), e)
+    });
+    assert!(screen == expected, "{} doesn't match {}, got\n{}", rom, golden.display(), screen);
+}
+
+#[test]
+fn test_bc_test() {
+    check("BC_test.ch8", "BC_test");
+}
+
+#[test]
+fn test_c8_test() {
+    check("c8_test.c8", "c8_test");
+}
+
+#[test]
+fn test_opcode() {
+    check("test_opcode.ch8", "test_opcode");
+}